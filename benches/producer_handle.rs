@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+
+// pushes per measured iteration, split evenly over the tasks
+const MESSAGES: u64 = 16384;
+
+const TASKS: [u64; 5] = [1, 4, 16, 64, 256];
+
+// answers the api versions handshake as a broker without it, so the producer falls back to v1 frames
+// everything after that is read and dropped, with acks 0 nothing is answered
+async fn serve(listener: TcpListener) {
+    while let Ok((socket, _)) = listener.accept().await {
+        tokio::spawn(discard(socket));
+    }
+}
+
+async fn discard(mut socket: TcpStream) {
+    let mut answered = false;
+
+    loop {
+        let mut len = [0u8; 8];
+        if socket.read_exact(&mut len).await.is_err() {
+            return;
+        }
+
+        let mut body = vec![0u8; u64::from_be_bytes(len) as usize];
+        if socket.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        if !answered {
+            // P, error code 35 for an unsupported version and a message
+            let mut response = vec![1, 1, b'P'];
+            response.extend_from_slice(&35i32.to_be_bytes());
+            response.extend_from_slice(&[1, 3, b'o', b'l', b'd']);
+
+            let mut frame = (response.len() as u64).to_be_bytes().to_vec();
+            frame.extend_from_slice(&response);
+            if socket.write_all(&frame).await.is_err() {
+                return;
+            }
+            answered = true;
+        }
+    }
+}
+
+async fn connect() -> Producer {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let servers = listener.local_addr().unwrap().to_string();
+    tokio::spawn(serve(listener));
+
+    let mut producer = Producer {
+        servers,
+        acks: Some("0".to_string()),
+        retries: Some(0),
+        pool: Some(4),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    producer
+}
+
+// the time until every push returned, the flush that follows is not measured
+fn concurrent_pushes(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let producer = Arc::new(Mutex::new(runtime.block_on(connect())));
+    let handle = runtime.block_on(async { producer.lock().await.handle().await.unwrap() });
+    let payload = Bytes::from(vec![7u8; 128]);
+
+    let mut group = c.benchmark_group("concurrent_pushes");
+    group.throughput(Throughput::Elements(MESSAGES));
+
+    for tasks in TASKS {
+        // every task clones the handle and pushes on its own
+        group.bench_with_input(BenchmarkId::new("handle", tasks), &tasks, |b, &tasks| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        let pushes: Vec<_> = (0..tasks).map(|_| {
+                            let handle = handle.clone();
+                            let payload = payload.clone();
+                            tokio::spawn(async move {
+                                for _ in 0..MESSAGES / tasks {
+                                    handle.push_bytes("bench", "key", payload.clone()).await.unwrap();
+                                }
+                            })
+                        }).collect();
+                        for push in pushes {
+                            push.await.unwrap();
+                        }
+                        elapsed += start.elapsed();
+
+                        producer.lock().await.flush(Duration::from_secs(30)).await.unwrap();
+                    }
+
+                    elapsed
+                })
+            });
+        });
+
+        // push takes &mut self, so tasks share the producer behind a mutex
+        group.bench_with_input(BenchmarkId::new("shared_producer", tasks), &tasks, |b, &tasks| {
+            b.iter_custom(|iters| {
+                runtime.block_on(async {
+                    let mut elapsed = Duration::ZERO;
+
+                    for _ in 0..iters {
+                        let start = Instant::now();
+                        let pushes: Vec<_> = (0..tasks).map(|_| {
+                            let producer = Arc::clone(&producer);
+                            let payload = payload.clone();
+                            tokio::spawn(async move {
+                                for _ in 0..MESSAGES / tasks {
+                                    producer.lock().await.push_bytes("bench", "key", payload.clone()).await.unwrap();
+                                }
+                            })
+                        }).collect();
+                        for push in pushes {
+                            push.await.unwrap();
+                        }
+                        elapsed += start.elapsed();
+
+                        producer.lock().await.flush(Duration::from_secs(30)).await.unwrap();
+                    }
+
+                    elapsed
+                })
+            });
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, concurrent_pushes);
+criterion_main!(benches);
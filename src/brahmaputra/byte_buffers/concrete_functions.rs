@@ -3,3 +3,20 @@ pub mod producers_objects;
 pub mod select_partition;
 pub mod simple_random;
 pub mod enums;
+pub mod compression;
+pub mod broker_error;
+pub mod producer_stream;
+pub mod tls;
+pub mod authentication;
+pub mod api_versions;
+pub mod producer_error;
+pub mod producer_builder;
+pub mod blocking_producer;
+pub mod serializers;
+pub mod typed_producer;
+pub mod interceptors;
+pub mod metrics;
+pub mod metrics_server;
+pub mod spill_journal;
+pub mod rate_limiter;
+pub mod producer_sink;
@@ -0,0 +1,137 @@
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::MessageCode;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::broker_api_versions;
+
+// frame versions the broker accepts for one message code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApiVersionRange {
+    pub min_version: i16,
+    pub max_version: i16,
+}
+
+// frame versions this client knows how to encode, sent to the broker in the handshake
+pub const CLIENT_API_VERSIONS: [(MessageCode, ApiVersionRange); 9] = [
+    // v2 adds the producer id, epoch and sequence number, v3 adds headers
+    (MessageCode::ProducerMsg, ApiVersionRange { min_version: 1, max_version: 3 }),
+    (MessageCode::InitProducerId, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::AddPartitionsToTxn, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::TxnOffsetCommit, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::EndTxn, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::Heartbeat, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::SaslHandshake, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::SaslAuthenticate, ApiVersionRange { min_version: 1, max_version: 1 }),
+    (MessageCode::ApiVersions, ApiVersionRange { min_version: 1, max_version: 1 }),
+];
+
+// brokers that predate the handshake only understand v1 produce frames
+pub const LEGACY_BROKER_API_VERSIONS: [(MessageCode, ApiVersionRange); 1] = [
+    (MessageCode::ProducerMsg, ApiVersionRange { min_version: 1, max_version: 1 }),
+];
+
+// features that need a minimum frame version on the broker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProducerFeature {
+    Idempotence,
+    Transactions,
+    Headers,
+}
+
+impl ProducerFeature {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProducerFeature::Idempotence => "idempotence",
+            ProducerFeature::Transactions => "transactions",
+            ProducerFeature::Headers => "headers",
+        }
+    }
+
+    // every message code the feature uses with the lowest version that supports it
+    pub fn required_versions(&self) -> &'static [(MessageCode, i16)] {
+        match self {
+            ProducerFeature::Idempotence => &[
+                (MessageCode::InitProducerId, 1),
+                (MessageCode::ProducerMsg, 2),
+            ],
+            ProducerFeature::Transactions => &[
+                (MessageCode::InitProducerId, 1),
+                (MessageCode::ProducerMsg, 2),
+                (MessageCode::AddPartitionsToTxn, 1),
+                (MessageCode::TxnOffsetCommit, 1),
+                (MessageCode::EndTxn, 1),
+            ],
+            ProducerFeature::Headers => &[
+                (MessageCode::ProducerMsg, 3),
+            ],
+        }
+    }
+}
+
+pub fn client_api_version(code: MessageCode) -> Option<ApiVersionRange> {
+    CLIENT_API_VERSIONS.iter().find(|(client_code, _)| *client_code == code).map(|(_, range)| *range)
+}
+
+pub fn broker_api_version(code: MessageCode) -> Option<ApiVersionRange> {
+    broker_api_versions.get(&(code as i32)).map(|entry| *entry.value())
+}
+
+// true once a broker connection finished the handshake and it listed the message code
+pub fn is_supported(code: MessageCode) -> bool {
+    negotiated_version(code).is_ok()
+}
+
+// highest version both sides support for the message code
+pub fn negotiated_version(code: MessageCode) -> Result<i16, ProducerError> {
+    if broker_api_versions.is_empty() {
+        return Err(ProducerError::NotConnected);
+    }
+
+    let client = match client_api_version(code) {
+        Some(client) => client,
+        None => {
+            return Err(ProducerError::UnsupportedVersion(format!("client cannot encode {:?} frames", code)));
+        }
+    };
+
+    let broker = match broker_api_version(code) {
+        Some(broker) => broker,
+        None => {
+            return Err(ProducerError::UnsupportedVersion(format!("broker does not support {:?} frames", code)));
+        }
+    };
+
+    let version = client.max_version.min(broker.max_version);
+    if version < client.min_version.max(broker.min_version) {
+        return Err(ProducerError::UnsupportedVersion(format!(
+            "no common {:?} version, client supports {} to {} and broker supports {} to {}",
+            code, client.min_version, client.max_version, broker.min_version, broker.max_version
+        )));
+    }
+
+    Ok(version)
+}
+
+// explains which frame the broker is too old for when a feature cannot be used
+pub fn require_feature(feature: ProducerFeature) -> Result<(), ProducerError> {
+    if broker_api_versions.is_empty() {
+        return Err(ProducerError::NotConnected);
+    }
+
+    for (code, required_version) in feature.required_versions() {
+        let supported = match broker_api_version(*code) {
+            Some(broker) => format!("versions {} to {}", broker.min_version, broker.max_version),
+            None => "no version".to_string(),
+        };
+
+        match negotiated_version(*code) {
+            Ok(version) if version >= *required_version => {}
+            _ => {
+                return Err(ProducerError::UnsupportedVersion(format!(
+                    "broker is too old for {}, it needs {:?} version {} but the broker supports {}",
+                    feature.name(), code, required_version, supported
+                )));
+            }
+        }
+    }
+
+    Ok(())
+}
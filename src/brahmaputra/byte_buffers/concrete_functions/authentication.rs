@@ -0,0 +1,360 @@
+use std::fmt;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// a broker cannot make the client spend seconds of cpu on every connection
+const MAX_SCRAM_ITERATIONS: u32 = 100000;
+
+#[derive(Debug)]
+pub enum AuthError {
+    // the broker does not support the requested mechanism
+    UnsupportedMechanism(String),
+    // the broker rejected the credentials
+    AuthenticationFailed(String),
+    // the broker answered with something the mechanism could not parse or verify
+    InvalidServerResponse(String),
+    // the token provider could not hand out a token
+    TokenUnavailable(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::UnsupportedMechanism(mechanism) => write!(f, "unsupported SASL mechanism: {}", mechanism),
+            AuthError::AuthenticationFailed(msg) => write!(f, "authentication failed: {}", msg),
+            AuthError::InvalidServerResponse(msg) => write!(f, "invalid server response: {}", msg),
+            AuthError::TokenUnavailable(msg) => write!(f, "token unavailable: {}", msg),
+            AuthError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AuthError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for AuthError {
+    fn from(err: std::io::Error) -> AuthError {
+        AuthError::Io(err)
+    }
+}
+
+// runs on every new connection before it is put in the pool
+pub trait Authenticator: Send + Sync + Debug {
+    fn mechanism(&self) -> String;
+
+    // a fresh exchange for every connection
+    fn start(&self) -> Result<Box<dyn SaslExchange>, AuthError>;
+}
+
+pub trait SaslExchange: Send {
+    // called first with an empty challenge, returns None once there is nothing left to send
+    fn step(&mut self, challenge: &[u8]) -> Result<Option<Vec<u8>>, AuthError>;
+}
+
+#[derive(Debug, Clone)]
+pub struct PlainAuthenticator {
+    pub username: String,
+    pub password: String,
+}
+
+impl Authenticator for PlainAuthenticator {
+    fn mechanism(&self) -> String {
+        "PLAIN".to_string()
+    }
+
+    fn start(&self) -> Result<Box<dyn SaslExchange>, AuthError> {
+        let mut initial_response = Vec::new();
+        initial_response.push(0);
+        initial_response.extend_from_slice(self.username.as_bytes());
+        initial_response.push(0);
+        initial_response.extend_from_slice(self.password.as_bytes());
+
+        Ok(Box::new(SingleStepExchange {
+            initial_response: Some(initial_response),
+        }))
+    }
+}
+
+struct SingleStepExchange {
+    initial_response: Option<Vec<u8>>,
+}
+
+impl SaslExchange for SingleStepExchange {
+    fn step(&mut self, _challenge: &[u8]) -> Result<Option<Vec<u8>>, AuthError> {
+        Ok(self.initial_response.take())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScramSha256Authenticator {
+    pub username: String,
+    pub password: String,
+}
+
+impl Authenticator for ScramSha256Authenticator {
+    fn mechanism(&self) -> String {
+        "SCRAM-SHA-256".to_string()
+    }
+
+    fn start(&self) -> Result<Box<dyn SaslExchange>, AuthError> {
+        Ok(Box::new(ScramExchange {
+            username: self.username.to_string(),
+            password: self.password.to_string(),
+            client_nonce: Uuid::new_v4().simple().to_string(),
+            client_first_bare: String::new(),
+            server_signature: Vec::new(),
+            state: ScramState::ClientFirst,
+        }))
+    }
+}
+
+enum ScramState {
+    ClientFirst,
+    ClientFinal,
+    ServerFinal,
+    Done,
+}
+
+struct ScramExchange {
+    username: String,
+    password: String,
+    client_nonce: String,
+    client_first_bare: String,
+    server_signature: Vec<u8>,
+    state: ScramState,
+}
+
+impl SaslExchange for ScramExchange {
+    fn step(&mut self, challenge: &[u8]) -> Result<Option<Vec<u8>>, AuthError> {
+        match self.state {
+            ScramState::ClientFirst => {
+                let username = self.username.replace('=', "=3D").replace(',', "=2C");
+                self.client_first_bare = format!("n={},r={}", username, self.client_nonce);
+                self.state = ScramState::ClientFinal;
+                Ok(Some(format!("n,,{}", self.client_first_bare).into_bytes()))
+            }
+            ScramState::ClientFinal => {
+                let server_first = String::from_utf8(challenge.to_vec())
+                    .map_err(|_| AuthError::InvalidServerResponse("server-first-message is not utf-8".to_string()))?;
+
+                let nonce = scram_attribute(&server_first, 'r')?;
+                let salt = STANDARD.decode(scram_attribute(&server_first, 's')?)
+                    .map_err(|err| AuthError::InvalidServerResponse(format!("invalid salt: {}", err)))?;
+                let iterations: u32 = scram_attribute(&server_first, 'i')?.parse()
+                    .map_err(|err| AuthError::InvalidServerResponse(format!("invalid iteration count: {}", err)))?;
+
+                if iterations == 0 || iterations > MAX_SCRAM_ITERATIONS {
+                    return Err(AuthError::InvalidServerResponse(format!("iteration count {} is not between 1 and {}", iterations, MAX_SCRAM_ITERATIONS)));
+                }
+
+                if !nonce.starts_with(&self.client_nonce) {
+                    return Err(AuthError::InvalidServerResponse("server nonce does not extend the client nonce".to_string()));
+                }
+
+                let mut salted_password = [0u8; 32];
+                pbkdf2::pbkdf2_hmac::<Sha256>(self.password.as_bytes(), &salt, iterations, &mut salted_password);
+
+                let client_key = hmac_sha256(&salted_password, b"Client Key");
+                let stored_key = Sha256::digest(&client_key);
+                let server_key = hmac_sha256(&salted_password, b"Server Key");
+
+                // biws is base64 of the gs2 header "n,,"
+                let client_final_without_proof = format!("c=biws,r={}", nonce);
+                let auth_message = format!("{},{},{}", self.client_first_bare, server_first, client_final_without_proof);
+
+                let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+                let client_proof: Vec<u8> = client_key.iter().zip(client_signature.iter()).map(|(key, signature)| key ^ signature).collect();
+
+                self.server_signature = hmac_sha256(&server_key, auth_message.as_bytes());
+                self.state = ScramState::ServerFinal;
+
+                Ok(Some(format!("{},p={}", client_final_without_proof, STANDARD.encode(client_proof)).into_bytes()))
+            }
+            ScramState::ServerFinal => {
+                let server_final = String::from_utf8(challenge.to_vec())
+                    .map_err(|_| AuthError::InvalidServerResponse("server-final-message is not utf-8".to_string()))?;
+
+                if let Ok(error) = scram_attribute(&server_final, 'e') {
+                    return Err(AuthError::AuthenticationFailed(error));
+                }
+
+                let verifier = STANDARD.decode(scram_attribute(&server_final, 'v')?)
+                    .map_err(|err| AuthError::InvalidServerResponse(format!("invalid server signature: {}", err)))?;
+
+                if verifier != self.server_signature {
+                    return Err(AuthError::InvalidServerResponse("server signature does not match".to_string()));
+                }
+
+                self.state = ScramState::Done;
+                Ok(None)
+            }
+            ScramState::Done => Ok(None),
+        }
+    }
+}
+
+fn scram_attribute(message: &str, name: char) -> Result<String, AuthError> {
+    message
+        .split(',')
+        .find_map(|attribute| attribute.strip_prefix(name).and_then(|value| value.strip_prefix('=')))
+        .map(|value| value.to_string())
+        .ok_or_else(|| AuthError::InvalidServerResponse(format!("missing attribute {} in {}", name, message)))
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[derive(Debug, Clone)]
+pub struct BearerToken {
+    pub value: String,
+    pub expires_at: Option<SystemTime>,
+}
+
+pub trait TokenProvider: Send + Sync + Debug {
+    fn token(&self) -> Result<BearerToken, AuthError>;
+}
+
+// a token that never changes, mostly for tests and long lived service tokens
+#[derive(Debug, Clone)]
+pub struct StaticTokenProvider {
+    pub token: String,
+}
+
+impl TokenProvider for StaticTokenProvider {
+    fn token(&self) -> Result<BearerToken, AuthError> {
+        Ok(BearerToken {
+            value: self.token.to_string(),
+            expires_at: None,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BearerTokenAuthenticator {
+    provider: Arc<dyn TokenProvider>,
+    refresh_before: Duration,
+    cached_token: Mutex<Option<BearerToken>>,
+}
+
+impl BearerTokenAuthenticator {
+    // tokens are fetched again once less than refresh_before is left before they expire
+    pub fn new(provider: Arc<dyn TokenProvider>, refresh_before: Duration) -> BearerTokenAuthenticator {
+        BearerTokenAuthenticator {
+            provider,
+            refresh_before,
+            cached_token: Mutex::new(None),
+        }
+    }
+
+    pub fn current_token(&self) -> Result<BearerToken, AuthError> {
+        let mut cached_token = self.cached_token.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(token) = cached_token.as_ref() {
+            let fresh = match token.expires_at {
+                Some(expires_at) => SystemTime::now() + self.refresh_before < expires_at,
+                None => true,
+            };
+            if fresh {
+                return Ok(token.clone());
+            }
+        }
+
+        let token = self.provider.token()?;
+        let _ = cached_token.insert(token.clone());
+        Ok(token)
+    }
+}
+
+impl Authenticator for BearerTokenAuthenticator {
+    fn mechanism(&self) -> String {
+        "OAUTHBEARER".to_string()
+    }
+
+    fn start(&self) -> Result<Box<dyn SaslExchange>, AuthError> {
+        let token = self.current_token()?;
+
+        Ok(Box::new(SingleStepExchange {
+            initial_response: Some(format!("n,,\x01auth=Bearer {}\x01\x01", token.value).into_bytes()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuthError, Authenticator, PlainAuthenticator, SaslExchange, ScramExchange, ScramState};
+
+    // the example exchange from RFC 7677 section 3
+    const CLIENT_NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+    const SERVER_FIRST: &str = "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const SERVER_FINAL: &str = "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+    fn rfc_exchange() -> ScramExchange {
+        ScramExchange {
+            username: "user".to_string(),
+            password: "pencil".to_string(),
+            client_nonce: CLIENT_NONCE.to_string(),
+            client_first_bare: String::new(),
+            server_signature: Vec::new(),
+            state: ScramState::ClientFirst,
+        }
+    }
+
+    #[test]
+    fn plain_sends_username_and_password_once() {
+        let authenticator = PlainAuthenticator {
+            username: "user".to_string(),
+            password: "pencil".to_string(),
+        };
+        let mut exchange = authenticator.start().unwrap();
+
+        assert_eq!(exchange.step(&[]).unwrap(), Some(b"\0user\0pencil".to_vec()));
+        assert_eq!(exchange.step(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn scram_matches_the_rfc_7677_example() {
+        let mut exchange = rfc_exchange();
+
+        assert_eq!(exchange.step(&[]).unwrap(), Some(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec()));
+        assert_eq!(exchange.step(SERVER_FIRST.as_bytes()).unwrap(), Some(CLIENT_FINAL.as_bytes().to_vec()));
+        assert_eq!(exchange.step(SERVER_FINAL.as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn scram_rejects_a_wrong_server_signature() {
+        let mut exchange = rfc_exchange();
+        exchange.step(&[]).unwrap();
+        exchange.step(SERVER_FIRST.as_bytes()).unwrap();
+
+        let forged = "v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+        assert!(matches!(exchange.step(forged.as_bytes()), Err(AuthError::InvalidServerResponse(_))));
+    }
+
+    #[test]
+    fn scram_rejects_iteration_counts_out_of_bounds() {
+        for iterations in ["0", "100000000"] {
+            let mut exchange = rfc_exchange();
+            exchange.step(&[]).unwrap();
+
+            let server_first = SERVER_FIRST.replace("i=4096", &format!("i={}", iterations));
+            assert!(matches!(exchange.step(server_first.as_bytes()), Err(AuthError::InvalidServerResponse(_))));
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crossbeam::channel::{bounded, Receiver, Sender};
+use tokio::runtime::Builder;
+use tracing::warn;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{DeliveryResult, Producer};
+
+// commands waiting for the runtime thread, send blocks once this many are queued
+const COMMAND_QUEUE_SIZE: usize = 1024;
+
+// how long dropping a producer that was never closed waits for it to flush
+const DROP_CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+enum Command {
+    Send {
+        topic: String,
+        key: String,
+        msg: Vec<u8>,
+        reply: Sender<DeliveryResult>,
+    },
+    SendAndWait {
+        topic: String,
+        key: String,
+        msg: Vec<u8>,
+        reply: Sender<DeliveryResult>,
+    },
+    Flush {
+        timeout: Duration,
+        reply: Sender<DeliveryResult>,
+    },
+    Close {
+        timeout: Duration,
+        reply: Sender<DeliveryResult>,
+    },
+}
+
+// a producer for synchronous code, it runs on its own runtime thread and can be shared between threads
+// the producer state is process wide, so only one can be connected at a time, share it instead of connecting another
+#[derive(Debug)]
+pub struct BlockingProducer {
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BlockingProducer {
+    // starts the runtime thread and connects the producer on it, fails while another producer is still connected
+    pub fn connect(producer: Producer) -> Result<BlockingProducer, ProducerError> {
+        let runtime = Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("brahmaputra-producer-worker")
+            .build()?;
+
+        let (commands, command_receiver) = bounded(COMMAND_QUEUE_SIZE);
+        let (connected_sender, connected) = bounded(1);
+
+        let thread = thread::Builder::new()
+            .name("brahmaputra-producer".to_string())
+            .spawn(move || {
+                runtime.block_on(run_producer(producer, command_receiver, connected_sender));
+            })?;
+
+        match connected.recv() {
+            Ok(Ok(_)) => Ok(BlockingProducer {
+                commands,
+                thread: Some(thread),
+            }),
+            Ok(Err(err)) => {
+                let _ = thread.join();
+                Err(err)
+            }
+            // the runtime thread died before it could answer
+            Err(_) => Err(ProducerError::NotConnected),
+        }
+    }
+
+    // blocks until the message is queued, or fails according to the backpressure policy
+    pub fn send(&self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.request(|reply| Command::Send { topic, key, msg, reply })
+    }
+
+    // blocks until the broker acked the message
+    pub fn send_and_wait(&self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.request(|reply| Command::SendAndWait { topic, key, msg, reply })
+    }
+
+    pub fn flush(&self, timeout: Duration) -> Result<(), ProducerError> {
+        self.request(|reply| Command::Flush { timeout, reply })
+    }
+
+    // flushes, closes the producer and stops the runtime thread
+    pub fn close(&mut self, timeout: Duration) -> Result<(), ProducerError> {
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        let closed = self.request(|reply| Command::Close { timeout, reply });
+        let _ = thread.join();
+        closed
+    }
+
+    fn request<F: FnOnce(Sender<DeliveryResult>) -> Command>(&self, command: F) -> Result<(), ProducerError> {
+        let (reply, response) = bounded(1);
+
+        if self.commands.send(command(reply)).is_err() {
+            return Err(ProducerError::NotConnected);
+        }
+
+        response.recv().unwrap_or(Err(ProducerError::NotConnected))
+    }
+}
+
+impl Drop for BlockingProducer {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            if let Err(err) = self.close(DROP_CLOSE_TIMEOUT) {
+                warn!(error = %err, "failed to close producer while dropping it");
+            }
+        }
+    }
+}
+
+// owns the producer, sends run on their own tasks so one waiting for buffer memory does not hold up the others
+// every caller waits for its reply, so the sends of one thread still go out in the order it made them
+async fn run_producer(mut producer: Producer, commands: Receiver<Command>, connected: Sender<DeliveryResult>) {
+    if let Err(err) = producer.connect_producer().await {
+        let _ = connected.send(Err(err));
+        return;
+    }
+    let _ = connected.send(Ok(()));
+
+    // transactional producers have no handle, their sends stay on this thread
+    let handle = producer.handle().await.ok();
+
+    // recv only blocks this thread, the producer tasks keep running on the runtime workers
+    while let Ok(command) = commands.recv() {
+        match command {
+            Command::Send { topic, key, msg, reply } => match &handle {
+                Some(handle) => {
+                    let handle = handle.clone();
+                    tokio::spawn(async move {
+                        let _ = reply.send(handle.push(topic, key, msg).await);
+                    });
+                }
+                None => {
+                    let _ = reply.send(producer.push(topic, key, msg).await);
+                }
+            },
+            Command::SendAndWait { topic, key, msg, reply } => {
+                let mut sender = producer.clone();
+                tokio::spawn(async move {
+                    let delivered = match sender.push_tracked(topic, key, msg.into()).await {
+                        Ok(delivery) => delivery.await.unwrap_or(Err(ProducerError::NotConnected)),
+                        Err(err) => Err(err),
+                    };
+                    let _ = reply.send(delivered);
+                });
+            }
+            Command::Flush { timeout, reply } => {
+                let _ = reply.send(producer.flush(timeout).await);
+            }
+            Command::Close { timeout, reply } => {
+                let _ = reply.send(producer.close(timeout).await);
+                return;
+            }
+        }
+    }
+}
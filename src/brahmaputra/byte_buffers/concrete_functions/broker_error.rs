@@ -0,0 +1,209 @@
+use std::fmt;
+
+// error codes the broker sends back in acks and control responses, 0 means success
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BrokerError {
+    UnknownServerError,
+    CorruptMessage,
+    UnknownTopicOrPartition,
+    LeaderNotAvailable,
+    NotLeaderForPartition,
+    RequestTimedOut,
+    MessageTooLarge,
+    NetworkException,
+    CoordinatorLoadInProgress,
+    CoordinatorNotAvailable,
+    NotCoordinator,
+    InvalidTopic,
+    RecordListTooLarge,
+    NotEnoughReplicas,
+    NotEnoughReplicasAfterAppend,
+    InvalidRequiredAcks,
+    TopicAuthorizationFailed,
+    ClusterAuthorizationFailed,
+    UnsupportedSaslMechanism,
+    IllegalSaslState,
+    UnsupportedVersion,
+    InvalidRequest,
+    UnsupportedForMessageFormat,
+    OutOfOrderSequenceNumber,
+    DuplicateSequenceNumber,
+    InvalidProducerEpoch,
+    InvalidTxnState,
+    TransactionalIdAuthorizationFailed,
+    StorageError,
+    AuthenticationFailed,
+    UnknownProducerId,
+    ThrottlingQuotaExceeded,
+    ProducerFenced,
+    Unknown(i32),
+}
+
+impl BrokerError {
+    // returns None for the success code
+    pub fn from_code(code: i32) -> Option<BrokerError> {
+        let error = match code {
+            0 => return None,
+            -1 => BrokerError::UnknownServerError,
+            2 => BrokerError::CorruptMessage,
+            3 => BrokerError::UnknownTopicOrPartition,
+            5 => BrokerError::LeaderNotAvailable,
+            6 => BrokerError::NotLeaderForPartition,
+            7 => BrokerError::RequestTimedOut,
+            10 => BrokerError::MessageTooLarge,
+            13 => BrokerError::NetworkException,
+            14 => BrokerError::CoordinatorLoadInProgress,
+            15 => BrokerError::CoordinatorNotAvailable,
+            16 => BrokerError::NotCoordinator,
+            17 => BrokerError::InvalidTopic,
+            18 => BrokerError::RecordListTooLarge,
+            19 => BrokerError::NotEnoughReplicas,
+            20 => BrokerError::NotEnoughReplicasAfterAppend,
+            21 => BrokerError::InvalidRequiredAcks,
+            29 => BrokerError::TopicAuthorizationFailed,
+            31 => BrokerError::ClusterAuthorizationFailed,
+            33 => BrokerError::UnsupportedSaslMechanism,
+            34 => BrokerError::IllegalSaslState,
+            35 => BrokerError::UnsupportedVersion,
+            42 => BrokerError::InvalidRequest,
+            43 => BrokerError::UnsupportedForMessageFormat,
+            45 => BrokerError::OutOfOrderSequenceNumber,
+            46 => BrokerError::DuplicateSequenceNumber,
+            47 => BrokerError::InvalidProducerEpoch,
+            48 => BrokerError::InvalidTxnState,
+            53 => BrokerError::TransactionalIdAuthorizationFailed,
+            56 => BrokerError::StorageError,
+            58 => BrokerError::AuthenticationFailed,
+            59 => BrokerError::UnknownProducerId,
+            89 => BrokerError::ThrottlingQuotaExceeded,
+            90 => BrokerError::ProducerFenced,
+            code => BrokerError::Unknown(code),
+        };
+        Some(error)
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            BrokerError::UnknownServerError => -1,
+            BrokerError::CorruptMessage => 2,
+            BrokerError::UnknownTopicOrPartition => 3,
+            BrokerError::LeaderNotAvailable => 5,
+            BrokerError::NotLeaderForPartition => 6,
+            BrokerError::RequestTimedOut => 7,
+            BrokerError::MessageTooLarge => 10,
+            BrokerError::NetworkException => 13,
+            BrokerError::CoordinatorLoadInProgress => 14,
+            BrokerError::CoordinatorNotAvailable => 15,
+            BrokerError::NotCoordinator => 16,
+            BrokerError::InvalidTopic => 17,
+            BrokerError::RecordListTooLarge => 18,
+            BrokerError::NotEnoughReplicas => 19,
+            BrokerError::NotEnoughReplicasAfterAppend => 20,
+            BrokerError::InvalidRequiredAcks => 21,
+            BrokerError::TopicAuthorizationFailed => 29,
+            BrokerError::ClusterAuthorizationFailed => 31,
+            BrokerError::UnsupportedSaslMechanism => 33,
+            BrokerError::IllegalSaslState => 34,
+            BrokerError::UnsupportedVersion => 35,
+            BrokerError::InvalidRequest => 42,
+            BrokerError::UnsupportedForMessageFormat => 43,
+            BrokerError::OutOfOrderSequenceNumber => 45,
+            BrokerError::DuplicateSequenceNumber => 46,
+            BrokerError::InvalidProducerEpoch => 47,
+            BrokerError::InvalidTxnState => 48,
+            BrokerError::TransactionalIdAuthorizationFailed => 53,
+            BrokerError::StorageError => 56,
+            BrokerError::AuthenticationFailed => 58,
+            BrokerError::UnknownProducerId => 59,
+            BrokerError::ThrottlingQuotaExceeded => 89,
+            BrokerError::ProducerFenced => 90,
+            BrokerError::Unknown(code) => *code,
+        }
+    }
+
+    // retriable errors are transient, sending the same frame again can succeed
+    // an out of order sequence means a frame before it is missing, sending this one again does not fill the gap
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            BrokerError::CorruptMessage
+                | BrokerError::UnknownTopicOrPartition
+                | BrokerError::LeaderNotAvailable
+                | BrokerError::NotLeaderForPartition
+                | BrokerError::RequestTimedOut
+                | BrokerError::NetworkException
+                | BrokerError::CoordinatorLoadInProgress
+                | BrokerError::CoordinatorNotAvailable
+                | BrokerError::NotCoordinator
+                | BrokerError::NotEnoughReplicas
+                | BrokerError::NotEnoughReplicasAfterAppend
+                | BrokerError::StorageError
+                | BrokerError::ThrottlingQuotaExceeded
+        )
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        !self.is_retriable()
+    }
+
+    // the producer id is no longer valid because a newer instance took it over
+    pub fn is_fenced(&self) -> bool {
+        matches!(self, BrokerError::ProducerFenced | BrokerError::InvalidProducerEpoch)
+    }
+}
+
+impl fmt::Display for BrokerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BrokerError::Unknown(code) => write!(f, "unknown broker error code {}", code),
+            error => write!(f, "{:?} ({})", error, error.code()),
+        }
+    }
+}
+
+impl std::error::Error for BrokerError {}
+
+#[cfg(test)]
+mod tests {
+    use super::BrokerError;
+
+    const KNOWN_CODES: [i32; 33] = [-1, 2, 3, 5, 6, 7, 10, 13, 14, 15, 16, 17, 18, 19, 20, 21, 29, 31, 33, 34, 35, 42, 43, 45, 46, 47, 48, 53, 56, 58, 59, 89, 90];
+
+    #[test]
+    fn codes_round_trip() {
+        assert_eq!(BrokerError::from_code(0), None);
+
+        for code in KNOWN_CODES {
+            let error = BrokerError::from_code(code).unwrap();
+            assert!(!matches!(error, BrokerError::Unknown(_)), "{} is a known code", code);
+            assert_eq!(error.code(), code);
+        }
+
+        assert_eq!(BrokerError::from_code(9999), Some(BrokerError::Unknown(9999)));
+        assert_eq!(BrokerError::Unknown(9999).code(), 9999);
+    }
+
+    #[test]
+    fn transient_errors_are_retriable() {
+        for error in [BrokerError::NotLeaderForPartition, BrokerError::RequestTimedOut, BrokerError::NotEnoughReplicas, BrokerError::ThrottlingQuotaExceeded] {
+            assert!(error.is_retriable(), "{}", error);
+            assert!(!error.is_fatal(), "{}", error);
+        }
+    }
+
+    #[test]
+    fn sequence_gaps_and_rejections_are_fatal() {
+        for error in [BrokerError::OutOfOrderSequenceNumber, BrokerError::MessageTooLarge, BrokerError::TopicAuthorizationFailed, BrokerError::Unknown(9999)] {
+            assert!(error.is_fatal(), "{}", error);
+            assert!(!error.is_fenced(), "{}", error);
+        }
+    }
+
+    #[test]
+    fn fencing_errors_are_fatal() {
+        for error in [BrokerError::ProducerFenced, BrokerError::InvalidProducerEpoch] {
+            assert!(error.is_fenced(), "{}", error);
+            assert!(error.is_fatal(), "{}", error);
+        }
+    }
+}
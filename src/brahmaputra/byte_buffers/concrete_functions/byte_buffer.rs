@@ -1,7 +1,7 @@
 use std::io::Write;
-use std::string::FromUtf8Error;
 use bytebuffer::ByteBuffer;
 use bytebuffer::Endian;
+use tracing::error;
 
 #[derive(Debug, Default)]
 pub struct ByteBuff {
@@ -56,21 +56,21 @@ impl ByteBuff {
 
     pub fn get(&mut self) -> Vec<u8> {
 
-        return match self.buffer.read_u64() {
+        match self.buffer.read_u64() {
             Ok(value) => {
                 let total_length = value as usize;
                 match self.buffer.read_bytes(total_length){
                     Ok(val) => {
-                        return val;
+                        val
                     }
                     Err(err) => {
-                        println!("{:?}", err);
+                        error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, payload_length = total_length, "failed to read payload bytes from frame");
                         vec![]
                     }
                 }
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read payload length from frame");
                 vec![]
             }
         }
@@ -78,7 +78,7 @@ impl ByteBuff {
 
     pub fn put(&mut self, value: Vec<u8>) {
         self.buffer.write_u64(value.len() as u64);
-        self.buffer.write(value.as_slice()).expect("failed to write");
+        self.buffer.write_all(value.as_slice()).expect("failed to write");
     }
 
     pub fn put_string(&mut self, value: String) {
@@ -107,71 +107,67 @@ impl ByteBuff {
     }
 
     pub fn get_short(&mut self) -> i16 {
-        return match self.buffer.read_u16() {
+        match self.buffer.read_u16() {
             Ok(value) => {
                 value as i16
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read i16 from frame");
                 0
             }
         }
     }
 
     pub fn get_int(&mut self) -> i32 {
-        return match self.buffer.read_u32() {
+        match self.buffer.read_u32() {
             Ok(value) => {
                 value as i32
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read i32 from frame");
                 0
             }
         }
     }
 
     pub fn get_long(&mut self) -> i64 {
-        return match self.buffer.read_u64() {
+        match self.buffer.read_u64() {
             Ok(value) => {
                 value as i64
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read i64 from frame");
                 0
             }
         }
     }
 
     pub fn get_float(&mut self) -> f64 {
-        return match self.buffer.read_u64() {
+        match self.buffer.read_u64() {
             Ok(value) => {
                 value as f64 / self.multiplier
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read float from frame");
                 0.0
             }
         }
     }
 
     pub fn get_bool(&mut self) -> bool {
-        return match self.buffer.read_u8() {
+        match self.buffer.read_u8() {
             Ok(value) => {
-                if value == 1 {
-                    true
-                } else {
-                    false
-                }
+                value == 1
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read bool from frame");
                 false
             }
         }
     }
 
     pub fn get_string(&mut self) -> String {
-        return match self.buffer.read_u8() {
+        match self.buffer.read_u8() {
             Ok(type_string) => {
                 let mut string_data = String::from("");
 
@@ -186,18 +182,18 @@ impl ByteBuff {
                                             string_data = val;
                                         }
                                         Err(err) => {
-                                            println!("{:?}", err);
+                                            error!(error = %err, "string in frame is not valid utf-8");
                                             return "".to_string();
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    println!("{:?}", e);
+                                    error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string bytes from frame");
                                 }
                             }
                         }
                         Err(e) => {
-                            println!("{:?}", e);
+                            error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string length from frame");
                         }
                     }
 
@@ -212,18 +208,18 @@ impl ByteBuff {
                                             string_data = val;
                                         }
                                         Err(err) => {
-                                            println!("{:?}", err);
+                                            error!(error = %err, "string in frame is not valid utf-8");
                                             return "".to_string();
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    println!("{:?}", e);
+                                    error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string bytes from frame");
                                 }
                             }
                         }
                         Err(e) => {
-                            println!("{:?}", e);
+                            error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string length from frame");
                         }
                     }
                 } else if type_string == 3 {
@@ -237,18 +233,18 @@ impl ByteBuff {
                                             string_data = val;
                                         }
                                         Err(err) => {
-                                            println!("{:?}", err);
+                                            error!(error = %err, "string in frame is not valid utf-8");
                                             return "".to_string();
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    println!("{:?}", e);
+                                    error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string bytes from frame");
                                 }
                             }
                         }
                         Err(e) => {
-                            println!("{:?}", e);
+                            error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string length from frame");
                         }
                     }
                 } else {
@@ -262,18 +258,18 @@ impl ByteBuff {
                                             string_data = val;
                                         }
                                         Err(err) => {
-                                            println!("{:?}", err);
+                                            error!(error = %err, "string in frame is not valid utf-8");
                                             return "".to_string();
                                         }
                                     }
                                 }
                                 Err(e) => {
-                                    println!("{:?}", e);
+                                    error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string bytes from frame");
                                 }
                             }
                         }
                         Err(e) => {
-                            println!("{:?}", e);
+                            error!(error = %e, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string length from frame");
                         }
                     }
                 }
@@ -285,13 +281,18 @@ impl ByteBuff {
                 }
             }
             Err(err) => {
-                println!("{:?}", err);
+                error!(error = %err, position = self.buffer.get_rpos(), length = self.total_buffer_length, "failed to read string type from frame");
                 "".to_string()
             }
         }
     }
 
+    // bytes left to read, lets a decoder check for fields newer peers append
+    pub fn remaining(&self) -> usize {
+        self.buffer.len().saturating_sub(self.buffer.get_rpos())
+    }
+
     pub fn to_array(&self) -> Vec<u8> {
-        return self.buffer.to_owned().into_vec();
+        self.buffer.to_owned().into_vec()
     }
 }
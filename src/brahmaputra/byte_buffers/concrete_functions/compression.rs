@@ -0,0 +1,138 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionType {
+    None,
+    Gzip,
+    Lz4,
+    Zstd,
+    Snappy,
+}
+
+impl CompressionType {
+    // parses the codec name used in the producer config, unknown names are rejected
+    pub fn from_name(name: &str) -> Result<CompressionType, Error> {
+        match name.to_lowercase().as_str() {
+            "" | "none" => Ok(CompressionType::None),
+            "gzip" => Ok(CompressionType::Gzip),
+            "lz4" => Ok(CompressionType::Lz4),
+            "zstd" => Ok(CompressionType::Zstd),
+            "snappy" => Ok(CompressionType::Snappy),
+            _ => Err(Error::new(ErrorKind::InvalidInput, format!("unknown compression type: {}", name))),
+        }
+    }
+
+    // name written into the frame header so the broker knows how to read the payload
+    pub fn name(&self) -> &'static str {
+        match self {
+            CompressionType::None => "none",
+            CompressionType::Gzip => "gzip",
+            CompressionType::Lz4 => "lz4",
+            CompressionType::Zstd => "zstd",
+            CompressionType::Snappy => "snappy",
+        }
+    }
+
+    pub fn compress(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            CompressionType::None => Ok(payload.to_vec()),
+            CompressionType::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(payload)?;
+                encoder.finish()
+            }
+            CompressionType::Lz4 => {
+                let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+                encoder.write_all(payload)?;
+                encoder.finish().map_err(Error::other)
+            }
+            CompressionType::Zstd => zstd::encode_all(payload, 0),
+            CompressionType::Snappy => {
+                snap::raw::Encoder::new().compress_vec(payload).map_err(Error::other)
+            }
+        }
+    }
+
+    pub fn decompress(&self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        match self {
+            CompressionType::None => Ok(payload.to_vec()),
+            CompressionType::Gzip => {
+                let mut decoded = Vec::new();
+                GzDecoder::new(payload).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            CompressionType::Lz4 => {
+                let mut decoded = Vec::new();
+                lz4_flex::frame::FrameDecoder::new(payload).read_to_end(&mut decoded)?;
+                Ok(decoded)
+            }
+            CompressionType::Zstd => zstd::decode_all(payload),
+            CompressionType::Snappy => {
+                snap::raw::Decoder::new().decompress_vec(payload).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressionType;
+
+    fn sample_payload() -> Vec<u8> {
+        "hello sudeep ".repeat(512).into_bytes()
+    }
+
+    fn round_trip(codec: CompressionType) {
+        let payload = sample_payload();
+        let compressed = codec.compress(&payload).unwrap();
+        if codec != CompressionType::None {
+            assert!(compressed.len() < payload.len());
+        }
+        assert_eq!(codec.decompress(&compressed).unwrap(), payload);
+    }
+
+    #[test]
+    fn gzip_round_trip() {
+        round_trip(CompressionType::Gzip);
+    }
+
+    #[test]
+    fn lz4_round_trip() {
+        round_trip(CompressionType::Lz4);
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        round_trip(CompressionType::Zstd);
+    }
+
+    #[test]
+    fn snappy_round_trip() {
+        round_trip(CompressionType::Snappy);
+    }
+
+    #[test]
+    fn none_round_trip() {
+        round_trip(CompressionType::None);
+    }
+
+    #[test]
+    fn empty_payload_round_trip() {
+        for codec in [CompressionType::Gzip, CompressionType::Lz4, CompressionType::Zstd, CompressionType::Snappy] {
+            let compressed = codec.compress(&[]).unwrap();
+            assert!(codec.decompress(&compressed).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn codec_names() {
+        for name in ["none", "gzip", "lz4", "zstd", "snappy"] {
+            assert_eq!(CompressionType::from_name(name).unwrap().name(), name);
+        }
+        assert_eq!(CompressionType::from_name("LZ4").unwrap(), CompressionType::Lz4);
+        assert!(CompressionType::from_name("brotli").is_err());
+    }
+}
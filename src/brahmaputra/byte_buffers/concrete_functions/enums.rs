@@ -1,5 +1,43 @@
+use serde::{Deserialize, Serialize};
 
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
 pub enum MessageCode {
     ProducerMsg = 1000,
-}
\ No newline at end of file
+    InitProducerId = 1001,
+    AddPartitionsToTxn = 1002,
+    TxnOffsetCommit = 1003,
+    EndTxn = 1004,
+    Heartbeat = 1005,
+    SaslHandshake = 1006,
+    SaslAuthenticate = 1007,
+    ApiVersions = 1008,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionState {
+    Uninitialized,
+    Ready,
+    InTransaction,
+    Committing,
+    Aborting,
+    // a message of the transaction failed for good, the transaction can only be aborted
+    AbortableError,
+    Fenced,
+}
+
+// what push does when the producer queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackpressurePolicy {
+    // waits for room until buffer_full_timeout_ms runs out
+    #[default]
+    Block,
+    // fails straight away with a queue full error
+    FailFast,
+    // makes room by dropping the message that has waited the longest
+    DropOldest,
+    // drops the message being pushed
+    DropNewest,
+}
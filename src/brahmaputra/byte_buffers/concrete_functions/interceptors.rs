@@ -0,0 +1,12 @@
+use std::fmt::Debug;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{DeliveryReport, ProducerRecord};
+
+// hooks registered on the producer, they run for every message in the order they were added
+pub trait ProducerInterceptor: Send + Sync + Debug {
+    // runs before the record is encoded, so headers, key and payload can still change
+    fn on_send(&self, record: &mut ProducerRecord);
+
+    // runs once for every message that went through on_send, when it was acked, rejected, dropped or given up on
+    // it is called from whichever task settled the message, keep it short
+    fn on_acknowledgement(&self, report: &DeliveryReport);
+}
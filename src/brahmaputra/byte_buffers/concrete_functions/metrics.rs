@@ -0,0 +1,246 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use dashmap::DashMap;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{buffer_budget, dropped_messages, in_flight_slots, pool_socket_writer, queued_frames, spill_journal, unacked_messages};
+
+// upper bounds of the ack latency buckets in milliseconds, the +Inf bucket is implied
+pub const ACK_LATENCY_BUCKETS_MS: [u64; 14] = [1, 2, 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
+// metric name, help text and the counter it reads
+type CounterDescription<T> = (&'static str, &'static str, fn(&T) -> &AtomicU64);
+
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; ACK_LATENCY_BUCKETS_MS.len()],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, value: Duration) {
+        let millis = value.as_millis() as u64;
+
+        // buckets only count their own range, render adds them up the way prometheus expects
+        if let Some(bucket) = ACK_LATENCY_BUCKETS_MS.iter().position(|bound| millis <= *bound) {
+            self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(value.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> Duration {
+        Duration::from_micros(self.sum_micros.load(Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TopicMetrics {
+    pub messages_sent: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub messages_acked: AtomicU64,
+    pub messages_failed: AtomicU64,
+    pub retries: AtomicU64,
+    // from the first write to the final ack, retries included
+    pub ack_latency: Histogram,
+}
+
+#[derive(Debug, Default)]
+pub struct ConnectionMetrics {
+    pub frames_written: AtomicU64,
+    pub bytes_written: AtomicU64,
+    pub frames_received: AtomicU64,
+    pub write_errors: AtomicU64,
+    pub reconnects: AtomicU64,
+    pub throttle_time_ms: AtomicU64,
+}
+
+// counters live for the whole process so scrapes never see them go backwards
+#[derive(Debug, Default)]
+pub struct ProducerMetrics {
+    topics: DashMap<String, Arc<TopicMetrics>>,
+    connections: DashMap<i32, Arc<ConnectionMetrics>>,
+    buffer_memory_limit: AtomicU64,
+}
+
+impl ProducerMetrics {
+    pub fn topic(&self, topic: &str) -> Arc<TopicMetrics> {
+        // looking up first so the hot path does not allocate the key
+        if let Some(metrics) = self.topics.get(topic) {
+            return Arc::clone(metrics.value());
+        }
+        Arc::clone(self.topics.entry(topic.to_string()).or_default().value())
+    }
+
+    pub fn connection(&self, conn_number: i32) -> Arc<ConnectionMetrics> {
+        if let Some(metrics) = self.connections.get(&conn_number) {
+            return Arc::clone(metrics.value());
+        }
+        Arc::clone(self.connections.entry(conn_number).or_default().value())
+    }
+
+    pub(crate) fn set_buffer_memory_limit(&self, bytes: u64) {
+        self.buffer_memory_limit.store(bytes, Ordering::Relaxed);
+    }
+
+    // bytes held by queued frames and frames waiting for acks
+    pub fn buffered_bytes(&self) -> u64 {
+        let available = match buffer_budget.try_read() {
+            Ok(budget) => match budget.as_ref() {
+                Some(budget) => budget.available_permits() as u64,
+                None => {
+                    return 0;
+                }
+            },
+            // the producer is connecting or closing
+            Err(_) => {
+                return 0;
+            }
+        };
+
+        self.buffer_memory_limit.load(Ordering::Relaxed).saturating_sub(available)
+    }
+
+    // segment files on disk, 0 without a spill journal
+    pub fn spilled_bytes(&self) -> u64 {
+        let journal = spill_journal.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        journal.as_ref().map(|journal| journal.total_bytes()).unwrap_or(0)
+    }
+
+    // written and waiting for their ack, whether or not the frame is kept for a retry
+    pub fn in_flight_frames(&self) -> u64 {
+        in_flight_slots.len() as u64
+    }
+
+    // everything in the prometheus text exposition format
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+
+        gauge(&mut out, "brahmaputra_producer_buffered_bytes", "Bytes held by queued and unacked frames.", self.buffered_bytes());
+        gauge(&mut out, "brahmaputra_producer_spilled_bytes", "Bytes held in spill journal segment files.", self.spilled_bytes());
+        gauge(&mut out, "brahmaputra_producer_in_flight_frames", "Frames written and waiting for their ack.", self.in_flight_frames());
+        gauge(&mut out, "brahmaputra_producer_queued_frames", "Frames waiting to be written.", queued_frames.load(Ordering::SeqCst) as u64);
+        gauge(&mut out, "brahmaputra_producer_unacked_messages", "Messages written but not acked yet.", unacked_messages.load(Ordering::SeqCst) as u64);
+        gauge(&mut out, "brahmaputra_producer_open_connections", "Pooled connections that are currently open.", pool_socket_writer.len() as u64);
+
+        header(&mut out, "brahmaputra_producer_dropped_messages_total", "Messages dropped by the backpressure policy since the producer connected.", "counter");
+        let _ = writeln!(out, "brahmaputra_producer_dropped_messages_total {}", dropped_messages.load(Ordering::SeqCst));
+
+        let mut topics: Vec<(String, Arc<TopicMetrics>)> = self.topics.iter().map(|entry| (entry.key().to_string(), Arc::clone(entry.value()))).collect();
+        topics.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let topic_counters: [CounterDescription<TopicMetrics>; 5] = [
+            ("brahmaputra_producer_messages_sent_total", "Messages written to a broker connection.", |metrics| &metrics.messages_sent),
+            ("brahmaputra_producer_bytes_sent_total", "Frame bytes written for messages.", |metrics| &metrics.bytes_sent),
+            ("brahmaputra_producer_messages_acked_total", "Messages the broker stored.", |metrics| &metrics.messages_acked),
+            ("brahmaputra_producer_messages_failed_total", "Messages the producer gave up on.", |metrics| &metrics.messages_failed),
+            ("brahmaputra_producer_retries_total", "Frames sent again after a retriable error.", |metrics| &metrics.retries),
+        ];
+        for (name, help, counter) in topic_counters {
+            header(&mut out, name, help, "counter");
+            for (topic, metrics) in &topics {
+                let _ = writeln!(out, "{}{{topic=\"{}\"}} {}", name, escape_label(topic), counter(metrics).load(Ordering::Relaxed));
+            }
+        }
+
+        let name = "brahmaputra_producer_ack_latency_seconds";
+        header(&mut out, name, "Time from the first write of a message to its final ack.", "histogram");
+        for (topic, metrics) in &topics {
+            let topic = escape_label(topic);
+            let mut cumulative = 0;
+            for (bound, bucket) in ACK_LATENCY_BUCKETS_MS.iter().zip(metrics.ack_latency.buckets.iter()) {
+                cumulative += bucket.load(Ordering::Relaxed);
+                let _ = writeln!(out, "{}_bucket{{topic=\"{}\",le=\"{}\"}} {}", name, topic, *bound as f64 / 1000.0, cumulative);
+            }
+            let _ = writeln!(out, "{}_bucket{{topic=\"{}\",le=\"+Inf\"}} {}", name, topic, metrics.ack_latency.count());
+            let _ = writeln!(out, "{}_sum{{topic=\"{}\"}} {}", name, topic, metrics.ack_latency.sum().as_secs_f64());
+            let _ = writeln!(out, "{}_count{{topic=\"{}\"}} {}", name, topic, metrics.ack_latency.count());
+        }
+
+        let mut connections: Vec<(i32, Arc<ConnectionMetrics>)> = self.connections.iter().map(|entry| (*entry.key(), Arc::clone(entry.value()))).collect();
+        connections.sort_by_key(|(conn_number, _)| *conn_number);
+
+        let connection_counters: [CounterDescription<ConnectionMetrics>; 6] = [
+            ("brahmaputra_producer_connection_frames_written_total", "Frames written on the connection.", |metrics| &metrics.frames_written),
+            ("brahmaputra_producer_connection_bytes_written_total", "Bytes written on the connection.", |metrics| &metrics.bytes_written),
+            ("brahmaputra_producer_connection_frames_received_total", "Frames read from the connection.", |metrics| &metrics.frames_received),
+            ("brahmaputra_producer_connection_write_errors_total", "Writes or flushes that failed and closed the connection.", |metrics| &metrics.write_errors),
+            ("brahmaputra_producer_connection_reconnects_total", "Times the connection was opened again.", |metrics| &metrics.reconnects),
+            ("brahmaputra_producer_connection_throttle_time_ms_total", "Milliseconds the broker asked the connection to pause.", |metrics| &metrics.throttle_time_ms),
+        ];
+        for (name, help, counter) in connection_counters {
+            header(&mut out, name, help, "counter");
+            for (conn_number, metrics) in &connections {
+                let _ = writeln!(out, "{}{{connection=\"{}\"}} {}", name, conn_number, counter(metrics).load(Ordering::Relaxed));
+            }
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, help, "gauge");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+    use super::ProducerMetrics;
+
+    #[test]
+    fn renders_counters_and_cumulative_histogram() {
+        let metrics = ProducerMetrics::default();
+
+        let topic = metrics.topic("orders \"eu\"");
+        topic.messages_acked.fetch_add(3, Ordering::Relaxed);
+        topic.ack_latency.observe(Duration::from_millis(1));
+        topic.ack_latency.observe(Duration::from_millis(20));
+        topic.ack_latency.observe(Duration::from_secs(60));
+        metrics.connection(2).write_errors.fetch_add(1, Ordering::Relaxed);
+
+        let rendered = metrics.render_prometheus();
+        let lines: Vec<&str> = rendered.lines().collect();
+
+        assert!(lines.contains(&"# TYPE brahmaputra_producer_messages_acked_total counter"));
+        assert!(lines.contains(&"brahmaputra_producer_messages_acked_total{topic=\"orders \\\"eu\\\"\"} 3"));
+        assert!(lines.contains(&"# TYPE brahmaputra_producer_ack_latency_seconds histogram"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_bucket{topic=\"orders \\\"eu\\\"\",le=\"0.001\"} 1"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_bucket{topic=\"orders \\\"eu\\\"\",le=\"0.025\"} 2"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_bucket{topic=\"orders \\\"eu\\\"\",le=\"30\"} 2"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_bucket{topic=\"orders \\\"eu\\\"\",le=\"+Inf\"} 3"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_sum{topic=\"orders \\\"eu\\\"\"} 60.021"));
+        assert!(lines.contains(&"brahmaputra_producer_ack_latency_seconds_count{topic=\"orders \\\"eu\\\"\"} 3"));
+        assert!(lines.contains(&"brahmaputra_producer_connection_write_errors_total{connection=\"2\"} 1"));
+
+        // every sample belongs to a metric announced before it
+        let mut announced = Vec::new();
+        for line in &lines {
+            match line.strip_prefix("# TYPE ") {
+                Some(declaration) => announced.push(declaration.split(' ').next().unwrap()),
+                None if !line.starts_with('#') => {
+                    let name = line.split(['{', ' ']).next().unwrap();
+                    assert!(announced.iter().any(|metric| name.starts_with(metric)), "{} has no TYPE line", name);
+                }
+                None => {}
+            }
+        }
+    }
+}
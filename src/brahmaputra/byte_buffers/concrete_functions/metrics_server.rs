@@ -0,0 +1,26 @@
+use std::io;
+use std::net::ToSocketAddrs;
+use actix_web::dev::ServerHandle;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::producer_metrics;
+
+async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(producer_metrics.render_prometheus())
+}
+
+// serves GET /metrics for prometheus to scrape, has to be called inside a tokio runtime
+// the handle stops the server, dropping it keeps the server running
+pub fn spawn_metrics_server<A: ToSocketAddrs>(addr: A) -> io::Result<ServerHandle> {
+    let server = HttpServer::new(|| App::new().route("/metrics", web::get().to(metrics)))
+        .workers(1)
+        .disable_signals()
+        .bind(addr)?
+        .run();
+
+    let handle = server.handle();
+    tokio::spawn(server);
+
+    Ok(handle)
+}
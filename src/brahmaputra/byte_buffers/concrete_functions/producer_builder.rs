@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use crate::brahmaputra::byte_buffers::concrete_functions::authentication::Authenticator;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+use crate::brahmaputra::byte_buffers::concrete_functions::interceptors::ProducerInterceptor;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use crate::brahmaputra::byte_buffers::concrete_functions::rate_limiter::RateLimit;
+use crate::brahmaputra::byte_buffers::concrete_functions::tls::TlsConfig;
+
+// prefix of the environment variables read by from_env, BRAHMAPUTRA_SERVERS sets servers and so on
+pub const ENV_PREFIX: &str = "BRAHMAPUTRA_";
+
+// defaults applied by build for every setting left unset
+//   servers                   required, host:port
+//   max_buffer_size           100000 messages
+//   buffer_memory_bytes       33554432 (32 MiB)
+//   buffer_full_timeout_ms    60000
+//   backpressure_policy       block
+//   compression_type          none
+//   acks                      all
+//   retries                   5
+//   retry_backoff_ms          100, capped at retry_backoff_max_ms 1000
+//   reconnect_backoff_ms      50, capped at reconnect_backoff_max_ms 1000
+//   pool                      1 connection
+//   enable_idempotence        false, on when a transactional_id is set
+//   transaction_timeout_ms    60000
+//   heartbeat_interval_ms     3000, 0 turns heartbeats off
+//   heartbeat_timeout_ms      10000
+//   spill_dir                 unset, no spilling to disk, not allowed with idempotence
+//                             only new pushes spill, frames already queued stay in memory
+//   spill_segment_bytes       67108864 (64 MiB) per segment file
+//   spill_max_bytes           1073741824 (1 GiB) across all segment files
+//   max_messages_per_sec      unlimited, same for max_bytes_per_sec and topic_rate_limits
+//   max_in_flight_per_connection  5, or 1 when retrying without idempotence so retries keep the order
+// message_timeout_ms, delivery_timeout_ms, batch_size and socket_keepalive_enable are passed through as they are
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProducerConfig {
+    pub servers: Option<String>,
+    pub max_buffer_size: Option<u64>,
+    pub message_timeout_ms: Option<u64>,
+    pub delivery_timeout_ms: Option<u64>,
+    pub batch_size: Option<u64>,
+    pub compression_type: Option<String>,
+    pub acks: Option<String>,
+    pub retries: Option<u8>,
+    pub retry_backoff_ms: Option<u64>,
+    pub retry_backoff_max_ms: Option<u64>,
+    pub reconnect_backoff_ms: Option<u64>,
+    pub reconnect_backoff_max_ms: Option<u64>,
+    pub socket_keepalive_enable: Option<bool>,
+    pub pool: Option<i32>,
+    pub enable_idempotence: Option<bool>,
+    pub transactional_id: Option<String>,
+    pub transaction_timeout_ms: Option<u64>,
+    pub heartbeat_interval_ms: Option<u64>,
+    pub heartbeat_timeout_ms: Option<u64>,
+    pub tls: Option<TlsConfig>,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub backpressure_policy: Option<BackpressurePolicy>,
+    pub buffer_full_timeout_ms: Option<u64>,
+    pub buffer_memory_bytes: Option<u64>,
+    pub spill_dir: Option<String>,
+    pub spill_segment_bytes: Option<u64>,
+    pub spill_max_bytes: Option<u64>,
+    pub max_messages_per_sec: Option<u64>,
+    pub max_bytes_per_sec: Option<u64>,
+    pub topic_rate_limits: Option<HashMap<String, RateLimit>>,
+    pub max_in_flight_per_connection: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProducerBuilder {
+    config: ProducerConfig,
+    authenticator: Option<Arc<dyn Authenticator>>,
+    interceptors: Vec<Arc<dyn ProducerInterceptor>>,
+}
+
+impl ProducerBuilder {
+    pub fn new() -> ProducerBuilder {
+        ProducerBuilder::default()
+    }
+
+    pub fn from_config(config: ProducerConfig) -> ProducerBuilder {
+        ProducerBuilder {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_yaml_str(yaml: &str) -> Result<ProducerBuilder, ProducerError> {
+        let config = serde_yml::from_str(yaml).map_err(|err| ProducerError::InvalidConfig(format!("yaml: {}", err)))?;
+        Ok(ProducerBuilder::from_config(config))
+    }
+
+    pub fn from_yaml_file<P: AsRef<Path>>(path: P) -> Result<ProducerBuilder, ProducerError> {
+        ProducerBuilder::from_yaml_str(&read_config_file(path.as_ref())?)
+    }
+
+    pub fn from_json_str(json: &str) -> Result<ProducerBuilder, ProducerError> {
+        let config = serde_json::from_str(json).map_err(|err| ProducerError::InvalidConfig(format!("json: {}", err)))?;
+        Ok(ProducerBuilder::from_config(config))
+    }
+
+    pub fn from_json_file<P: AsRef<Path>>(path: P) -> Result<ProducerBuilder, ProducerError> {
+        ProducerBuilder::from_json_str(&read_config_file(path.as_ref())?)
+    }
+
+    pub fn from_env() -> Result<ProducerBuilder, ProducerError> {
+        ProducerBuilder::new().with_env()
+    }
+
+    // environment variables win over whatever was loaded before, so a file can be overridden per deployment
+    pub fn with_env(self) -> Result<ProducerBuilder, ProducerError> {
+        self.with_vars(std::env::vars())
+    }
+
+    pub fn with_vars<I: IntoIterator<Item = (String, String)>>(mut self, vars: I) -> Result<ProducerBuilder, ProducerError> {
+        for (name, value) in vars {
+            if let Some(setting) = name.strip_prefix(ENV_PREFIX) {
+                let applied = self.config.apply_var(&setting.to_lowercase(), &value)
+                    .map_err(|err| ProducerError::InvalidConfig(format!("{}: {}", name, err)))?;
+
+                // the prefix is shared with other brahmaputra tools, so a variable that is not a producer setting is only a warning
+                if !applied {
+                    warn!(variable = %name, "ignoring unknown producer setting");
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn servers<S: Into<String>>(mut self, servers: S) -> ProducerBuilder {
+        self.config.servers = Some(servers.into());
+        self
+    }
+
+    pub fn max_buffer_size(mut self, max_buffer_size: u64) -> ProducerBuilder {
+        self.config.max_buffer_size = Some(max_buffer_size);
+        self
+    }
+
+    pub fn message_timeout_ms(mut self, message_timeout_ms: u64) -> ProducerBuilder {
+        self.config.message_timeout_ms = Some(message_timeout_ms);
+        self
+    }
+
+    pub fn delivery_timeout_ms(mut self, delivery_timeout_ms: u64) -> ProducerBuilder {
+        self.config.delivery_timeout_ms = Some(delivery_timeout_ms);
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: u64) -> ProducerBuilder {
+        self.config.batch_size = Some(batch_size);
+        self
+    }
+
+    pub fn compression_type<S: Into<String>>(mut self, compression_type: S) -> ProducerBuilder {
+        self.config.compression_type = Some(compression_type.into());
+        self
+    }
+
+    pub fn acks<S: Into<String>>(mut self, acks: S) -> ProducerBuilder {
+        self.config.acks = Some(acks.into());
+        self
+    }
+
+    pub fn retries(mut self, retries: u8) -> ProducerBuilder {
+        self.config.retries = Some(retries);
+        self
+    }
+
+    pub fn retry_backoff_ms(mut self, retry_backoff_ms: u64) -> ProducerBuilder {
+        self.config.retry_backoff_ms = Some(retry_backoff_ms);
+        self
+    }
+
+    pub fn retry_backoff_max_ms(mut self, retry_backoff_max_ms: u64) -> ProducerBuilder {
+        self.config.retry_backoff_max_ms = Some(retry_backoff_max_ms);
+        self
+    }
+
+    pub fn reconnect_backoff_ms(mut self, reconnect_backoff_ms: u64) -> ProducerBuilder {
+        self.config.reconnect_backoff_ms = Some(reconnect_backoff_ms);
+        self
+    }
+
+    pub fn reconnect_backoff_max_ms(mut self, reconnect_backoff_max_ms: u64) -> ProducerBuilder {
+        self.config.reconnect_backoff_max_ms = Some(reconnect_backoff_max_ms);
+        self
+    }
+
+    pub fn socket_keepalive_enable(mut self, socket_keepalive_enable: bool) -> ProducerBuilder {
+        self.config.socket_keepalive_enable = Some(socket_keepalive_enable);
+        self
+    }
+
+    pub fn pool(mut self, pool: i32) -> ProducerBuilder {
+        self.config.pool = Some(pool);
+        self
+    }
+
+    pub fn enable_idempotence(mut self, enable_idempotence: bool) -> ProducerBuilder {
+        self.config.enable_idempotence = Some(enable_idempotence);
+        self
+    }
+
+    pub fn transactional_id<S: Into<String>>(mut self, transactional_id: S) -> ProducerBuilder {
+        self.config.transactional_id = Some(transactional_id.into());
+        self
+    }
+
+    pub fn transaction_timeout_ms(mut self, transaction_timeout_ms: u64) -> ProducerBuilder {
+        self.config.transaction_timeout_ms = Some(transaction_timeout_ms);
+        self
+    }
+
+    pub fn heartbeat_interval_ms(mut self, heartbeat_interval_ms: u64) -> ProducerBuilder {
+        self.config.heartbeat_interval_ms = Some(heartbeat_interval_ms);
+        self
+    }
+
+    // also how long a new connection waits for the api versions handshake before assuming an older broker
+    pub fn heartbeat_timeout_ms(mut self, heartbeat_timeout_ms: u64) -> ProducerBuilder {
+        self.config.heartbeat_timeout_ms = Some(heartbeat_timeout_ms);
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsConfig) -> ProducerBuilder {
+        self.config.tls = Some(tls);
+        self
+    }
+
+    pub fn sasl<M: Into<String>, U: Into<String>, P: Into<String>>(mut self, mechanism: M, username: U, password: P) -> ProducerBuilder {
+        self.config.sasl_mechanism = Some(mechanism.into());
+        self.config.sasl_username = Some(username.into());
+        self.config.sasl_password = Some(password.into());
+        self
+    }
+
+    pub fn authenticator(mut self, authenticator: Arc<dyn Authenticator>) -> ProducerBuilder {
+        self.authenticator = Some(authenticator);
+        self
+    }
+
+    // interceptors run in the order they were added
+    pub fn interceptor(mut self, interceptor: Arc<dyn ProducerInterceptor>) -> ProducerBuilder {
+        self.interceptors.push(interceptor);
+        self
+    }
+
+    pub fn backpressure_policy(mut self, backpressure_policy: BackpressurePolicy) -> ProducerBuilder {
+        self.config.backpressure_policy = Some(backpressure_policy);
+        self
+    }
+
+    pub fn buffer_full_timeout_ms(mut self, buffer_full_timeout_ms: u64) -> ProducerBuilder {
+        self.config.buffer_full_timeout_ms = Some(buffer_full_timeout_ms);
+        self
+    }
+
+    pub fn buffer_memory_bytes(mut self, buffer_memory_bytes: u64) -> ProducerBuilder {
+        self.config.buffer_memory_bytes = Some(buffer_memory_bytes);
+        self
+    }
+
+    // messages that cannot be held in memory or written are kept in segment files under this directory
+    pub fn spill_dir<S: Into<String>>(mut self, spill_dir: S) -> ProducerBuilder {
+        self.config.spill_dir = Some(spill_dir.into());
+        self
+    }
+
+    pub fn spill_segment_bytes(mut self, spill_segment_bytes: u64) -> ProducerBuilder {
+        self.config.spill_segment_bytes = Some(spill_segment_bytes);
+        self
+    }
+
+    pub fn spill_max_bytes(mut self, spill_max_bytes: u64) -> ProducerBuilder {
+        self.config.spill_max_bytes = Some(spill_max_bytes);
+        self
+    }
+
+    // limits for everything the producer sends, a message waits until both allow it
+    pub fn max_messages_per_sec(mut self, max_messages_per_sec: u64) -> ProducerBuilder {
+        self.config.max_messages_per_sec = Some(max_messages_per_sec);
+        self
+    }
+
+    pub fn max_bytes_per_sec(mut self, max_bytes_per_sec: u64) -> ProducerBuilder {
+        self.config.max_bytes_per_sec = Some(max_bytes_per_sec);
+        self
+    }
+
+    // applies on top of the producer wide limits
+    pub fn topic_rate_limit<S: Into<String>>(mut self, topic: S, limit: RateLimit) -> ProducerBuilder {
+        self.config.topic_rate_limits.get_or_insert_with(HashMap::new).insert(topic.into(), limit);
+        self
+    }
+
+    // frames a connection has written and not got the final ack for, every partition sticks to one connection
+    pub fn max_in_flight_per_connection(mut self, max_in_flight_per_connection: u64) -> ProducerBuilder {
+        self.config.max_in_flight_per_connection = Some(max_in_flight_per_connection);
+        self
+    }
+
+    pub fn build(self) -> Result<Producer, ProducerError> {
+        let config = self.config;
+
+        let producer = Producer {
+            servers: config.servers.ok_or_else(|| ProducerError::InvalidConfig("servers is required".to_string()))?,
+            max_buffer_size: Some(config.max_buffer_size.unwrap_or(100000)),
+            message_timeout_ms: config.message_timeout_ms,
+            delivery_timeout_ms: config.delivery_timeout_ms,
+            batch_size: config.batch_size,
+            compression_type: Some(config.compression_type.unwrap_or_else(|| "none".to_string())),
+            acks: Some(config.acks.unwrap_or_else(|| "all".to_string())),
+            retries: Some(config.retries.unwrap_or(5)),
+            retry_backoff_ms: Some(config.retry_backoff_ms.unwrap_or(100)),
+            retry_backoff_max_ms: Some(config.retry_backoff_max_ms.unwrap_or(1000)),
+            reconnect_backoff_ms: Some(config.reconnect_backoff_ms.unwrap_or(50)),
+            reconnect_backoff_max_ms: Some(config.reconnect_backoff_max_ms.unwrap_or(1000)),
+            socket_keepalive_enable: config.socket_keepalive_enable,
+            pool: Some(config.pool.unwrap_or(1)),
+            enable_idempotence: config.enable_idempotence,
+            transactional_id: config.transactional_id,
+            transaction_timeout_ms: Some(config.transaction_timeout_ms.unwrap_or(60000)),
+            heartbeat_interval_ms: Some(config.heartbeat_interval_ms.unwrap_or(3000)),
+            heartbeat_timeout_ms: Some(config.heartbeat_timeout_ms.unwrap_or(10000)),
+            tls: config.tls,
+            sasl_mechanism: config.sasl_mechanism,
+            sasl_username: config.sasl_username,
+            sasl_password: config.sasl_password,
+            authenticator: self.authenticator,
+            backpressure_policy: Some(config.backpressure_policy.unwrap_or_default()),
+            buffer_full_timeout_ms: Some(config.buffer_full_timeout_ms.unwrap_or(60000)),
+            buffer_memory_bytes: Some(config.buffer_memory_bytes.unwrap_or(33554432)),
+            spill_dir: config.spill_dir,
+            spill_segment_bytes: Some(config.spill_segment_bytes.unwrap_or(67108864)),
+            spill_max_bytes: Some(config.spill_max_bytes.unwrap_or(1073741824)),
+            max_messages_per_sec: config.max_messages_per_sec,
+            max_bytes_per_sec: config.max_bytes_per_sec,
+            topic_rate_limits: config.topic_rate_limits,
+            // the default depends on retries and idempotence, so it is left to the producer
+            max_in_flight_per_connection: config.max_in_flight_per_connection,
+            interceptors: self.interceptors,
+        };
+
+        producer.validate()?;
+        Ok(producer)
+    }
+}
+
+impl ProducerConfig {
+    // setting is the variable name without the prefix, lowercased, false when it is not a producer setting
+    fn apply_var(&mut self, setting: &str, value: &str) -> Result<bool, String> {
+        match setting {
+            "servers" => self.servers = Some(value.to_string()),
+            "max_buffer_size" => self.max_buffer_size = Some(parse_var(value)?),
+            "message_timeout_ms" => self.message_timeout_ms = Some(parse_var(value)?),
+            "delivery_timeout_ms" => self.delivery_timeout_ms = Some(parse_var(value)?),
+            "batch_size" => self.batch_size = Some(parse_var(value)?),
+            "compression_type" => self.compression_type = Some(value.to_string()),
+            "acks" => self.acks = Some(value.to_string()),
+            "retries" => self.retries = Some(parse_var(value)?),
+            "retry_backoff_ms" => self.retry_backoff_ms = Some(parse_var(value)?),
+            "retry_backoff_max_ms" => self.retry_backoff_max_ms = Some(parse_var(value)?),
+            "reconnect_backoff_ms" => self.reconnect_backoff_ms = Some(parse_var(value)?),
+            "reconnect_backoff_max_ms" => self.reconnect_backoff_max_ms = Some(parse_var(value)?),
+            "socket_keepalive_enable" => self.socket_keepalive_enable = Some(parse_var(value)?),
+            "pool" => self.pool = Some(parse_var(value)?),
+            "enable_idempotence" => self.enable_idempotence = Some(parse_var(value)?),
+            "transactional_id" => self.transactional_id = Some(value.to_string()),
+            "transaction_timeout_ms" => self.transaction_timeout_ms = Some(parse_var(value)?),
+            "heartbeat_interval_ms" => self.heartbeat_interval_ms = Some(parse_var(value)?),
+            "heartbeat_timeout_ms" => self.heartbeat_timeout_ms = Some(parse_var(value)?),
+            "tls_ca_location" => self.tls.get_or_insert_with(TlsConfig::default).ca_location = Some(value.to_string()),
+            "tls_certificate_location" => self.tls.get_or_insert_with(TlsConfig::default).certificate_location = Some(value.to_string()),
+            "tls_key_location" => self.tls.get_or_insert_with(TlsConfig::default).key_location = Some(value.to_string()),
+            "tls_server_name" => self.tls.get_or_insert_with(TlsConfig::default).server_name = Some(value.to_string()),
+            "tls_insecure_skip_verify" => self.tls.get_or_insert_with(TlsConfig::default).insecure_skip_verify = parse_var(value)?,
+            "sasl_mechanism" => self.sasl_mechanism = Some(value.to_string()),
+            "sasl_username" => self.sasl_username = Some(value.to_string()),
+            "sasl_password" => self.sasl_password = Some(value.to_string()),
+            "backpressure_policy" => {
+                let policy = serde_json::Value::String(value.to_lowercase());
+                self.backpressure_policy = Some(serde_json::from_value(policy).map_err(|_| {
+                    format!("unknown backpressure policy {}, expected block, fail_fast, drop_oldest or drop_newest", value)
+                })?);
+            }
+            "buffer_full_timeout_ms" => self.buffer_full_timeout_ms = Some(parse_var(value)?),
+            "buffer_memory_bytes" => self.buffer_memory_bytes = Some(parse_var(value)?),
+            "spill_dir" => self.spill_dir = Some(value.to_string()),
+            "spill_segment_bytes" => self.spill_segment_bytes = Some(parse_var(value)?),
+            "spill_max_bytes" => self.spill_max_bytes = Some(parse_var(value)?),
+            "max_messages_per_sec" => self.max_messages_per_sec = Some(parse_var(value)?),
+            "max_bytes_per_sec" => self.max_bytes_per_sec = Some(parse_var(value)?),
+            // a json object from topic to its limits, {"logs": {"messages_per_sec": 100}}
+            "topic_rate_limits" => self.topic_rate_limits = Some(serde_json::from_str(value).map_err(|err| format!("invalid topic rate limits {}: {}", value, err))?),
+            "max_in_flight_per_connection" => self.max_in_flight_per_connection = Some(parse_var(value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn parse_var<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    value.trim().parse().map_err(|err| format!("invalid value {}: {}", value, err))
+}
+
+fn read_config_file(path: &Path) -> Result<String, ProducerError> {
+    fs::read_to_string(path).map_err(|err| ProducerError::InvalidConfig(format!("{}: {}", path.display(), err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProducerBuilder;
+    use crate::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+    use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+    use crate::brahmaputra::byte_buffers::concrete_functions::rate_limiter::RateLimit;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn invalid_config(result: Result<ProducerBuilder, ProducerError>) -> String {
+        match result {
+            Err(ProducerError::InvalidConfig(msg)) => msg,
+            other => panic!("expected an invalid config error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn build_fills_in_the_defaults() {
+        let producer = ProducerBuilder::new().servers("localhost:9092").build().unwrap();
+
+        assert_eq!(producer.max_buffer_size, Some(100000));
+        assert_eq!(producer.compression_type.as_deref(), Some("none"));
+        assert_eq!(producer.acks.as_deref(), Some("all"));
+        assert_eq!(producer.retries, Some(5));
+        assert_eq!((producer.retry_backoff_ms, producer.retry_backoff_max_ms), (Some(100), Some(1000)));
+        assert_eq!((producer.reconnect_backoff_ms, producer.reconnect_backoff_max_ms), (Some(50), Some(1000)));
+        assert_eq!(producer.pool, Some(1));
+        assert_eq!(producer.enable_idempotence, None);
+        assert_eq!(producer.transaction_timeout_ms, Some(60000));
+        assert_eq!((producer.heartbeat_interval_ms, producer.heartbeat_timeout_ms), (Some(3000), Some(10000)));
+        assert_eq!(producer.backpressure_policy, Some(BackpressurePolicy::Block));
+        assert_eq!(producer.buffer_full_timeout_ms, Some(60000));
+        assert_eq!(producer.buffer_memory_bytes, Some(33554432));
+        assert_eq!(producer.spill_dir, None);
+        assert_eq!((producer.spill_segment_bytes, producer.spill_max_bytes), (Some(67108864), Some(1073741824)));
+        assert_eq!(producer.max_in_flight_per_connection, None);
+    }
+
+    #[test]
+    fn build_requires_servers() {
+        assert!(matches!(ProducerBuilder::new().build(), Err(ProducerError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn env_vars_override_the_file_and_ignore_other_prefixes() {
+        let builder = ProducerBuilder::from_yaml_str("servers: file:9092\npool: 2\nretries: 3\n").unwrap();
+        let producer = builder
+            .with_vars(vars(&[
+                ("BRAHMAPUTRA_SERVERS", "env:9092"),
+                ("BRAHMAPUTRA_POOL", " 4 "),
+                ("BRAHMAPUTRA_BACKPRESSURE_POLICY", "Fail_Fast"),
+                ("BRAHMAPUTRA_TLS_INSECURE_SKIP_VERIFY", "true"),
+                ("BRAHMAPUTRA_TOPIC_RATE_LIMITS", r#"{"logs": {"messages_per_sec": 100}}"#),
+                ("PATH", "/usr/bin"),
+            ]))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(producer.servers, "env:9092");
+        assert_eq!(producer.pool, Some(4));
+        assert_eq!(producer.retries, Some(3));
+        assert_eq!(producer.backpressure_policy, Some(BackpressurePolicy::FailFast));
+        assert!(producer.tls.unwrap().insecure_skip_verify);
+        assert_eq!(producer.topic_rate_limits.unwrap()["logs"], RateLimit { messages_per_sec: Some(100), bytes_per_sec: None });
+    }
+
+    #[test]
+    fn env_vars_with_bad_values_name_the_variable() {
+        let msg = invalid_config(ProducerBuilder::new().with_vars(vars(&[("BRAHMAPUTRA_POOL", "many")])));
+        assert!(msg.starts_with("BRAHMAPUTRA_POOL: invalid value many"), "{}", msg);
+
+        let msg = invalid_config(ProducerBuilder::new().with_vars(vars(&[("BRAHMAPUTRA_BACKPRESSURE_POLICY", "wait")])));
+        assert!(msg.contains("unknown backpressure policy wait"), "{}", msg);
+    }
+
+    #[test]
+    fn unknown_env_vars_are_skipped() {
+        let producer = ProducerBuilder::new()
+            .with_vars(vars(&[
+                ("BRAHMAPUTRA_HOME", "/opt/brahmaputra"),
+                ("BRAHMAPUTRA_LOG", "debug"),
+                ("BRAHMAPUTRA_SERVERS", "env:9092"),
+            ]))
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(producer.servers, "env:9092");
+    }
+
+    #[test]
+    fn config_files_reject_unknown_fields() {
+        let msg = invalid_config(ProducerBuilder::from_yaml_str("servers: localhost:9092\nserver: localhost:9093\n"));
+        assert!(msg.starts_with("yaml:") && msg.contains("unknown field `server`"), "{}", msg);
+
+        let msg = invalid_config(ProducerBuilder::from_json_str(r#"{"servers": "localhost:9092", "tls": {"verify": false}}"#));
+        assert!(msg.starts_with("json:") && msg.contains("unknown field `verify`"), "{}", msg);
+    }
+
+    #[test]
+    fn rejects_spilling_with_idempotence() {
+        let result = ProducerBuilder::new()
+            .servers("localhost:9092")
+            .enable_idempotence(true)
+            .spill_dir("/tmp/brahmaputra-spill")
+            .build();
+
+        assert!(matches!(result, Err(ProducerError::InvalidConfig(_))));
+    }
+}
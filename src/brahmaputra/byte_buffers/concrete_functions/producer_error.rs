@@ -0,0 +1,104 @@
+use std::fmt;
+use crate::brahmaputra::byte_buffers::concrete_functions::authentication::AuthError;
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::serializers::SerializationError;
+
+// everything the public producer API can fail with
+#[derive(Debug)]
+pub enum ProducerError {
+    // connect_producer was never called or the producer has been closed
+    NotConnected,
+    // not a single pooled connection could be opened, holds the last failure
+    AllBrokersDown(Box<ProducerError>),
+    // the queue or its buffer memory is full and the backpressure policy does not wait
+    QueueFull,
+    // the message would go over a rate limit and the call does not wait
+    RateLimited,
+    // waited longer than the configured timeout
+    Timeout(String),
+    // the message could not be turned into a frame
+    Encode(String),
+    // a typed key or value could not be serialized
+    Serialization(SerializationError),
+    // the broker rejected the request
+    Broker(BrokerError, String),
+    // settings that cannot work together
+    InvalidConfig(String),
+    // the broker is too old for a configured feature
+    UnsupportedVersion(String),
+    // a newer instance with the same transactional id has taken over
+    Fenced,
+    // the transaction is not in the state the call needs
+    InvalidTransactionState(String),
+    // a message of the transaction failed, it has to be aborted
+    TransactionFailed,
+    // start_send on a ProducerSink whose previous push, flush or close has not finished, poll_ready has to return Ready first
+    SinkNotReady,
+    Auth(AuthError),
+    Io(std::io::Error),
+}
+
+impl ProducerError {
+    // true when the same call can succeed later without changing the producer
+    pub fn is_retriable(&self) -> bool {
+        match self {
+            ProducerError::AllBrokersDown(_) | ProducerError::QueueFull | ProducerError::RateLimited | ProducerError::Timeout(_) | ProducerError::Io(_) => true,
+            ProducerError::Broker(err, _) => err.is_retriable(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ProducerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProducerError::NotConnected => write!(f, "producer is not connected"),
+            ProducerError::AllBrokersDown(err) => write!(f, "every broker connection failed, last error: {}", err),
+            ProducerError::QueueFull => write!(f, "producer queue is full"),
+            ProducerError::RateLimited => write!(f, "producer rate limit reached"),
+            ProducerError::Timeout(msg) => write!(f, "timed out {}", msg),
+            ProducerError::Encode(msg) => write!(f, "failed to encode message: {}", msg),
+            ProducerError::Serialization(err) => write!(f, "failed to serialize message: {}", err),
+            ProducerError::Broker(err, msg) => write!(f, "broker error {}: {}", err, msg),
+            ProducerError::InvalidConfig(msg) => write!(f, "invalid producer config: {}", msg),
+            ProducerError::UnsupportedVersion(msg) => write!(f, "{}", msg),
+            ProducerError::Fenced => write!(f, "producer has been fenced by a newer instance with the same transactional id"),
+            ProducerError::InvalidTransactionState(msg) => write!(f, "invalid transaction state: {}", msg),
+            ProducerError::TransactionFailed => write!(f, "a message of the transaction failed, the transaction has to be aborted"),
+            ProducerError::SinkNotReady => write!(f, "start_send called before poll_ready returned Ready"),
+            ProducerError::Auth(err) => write!(f, "{}", err),
+            ProducerError::Io(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for ProducerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProducerError::AllBrokersDown(err) => Some(err.as_ref()),
+            ProducerError::Broker(err, _) => Some(err),
+            ProducerError::Serialization(err) => Some(err),
+            ProducerError::Auth(err) => Some(err),
+            ProducerError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ProducerError {
+    fn from(err: std::io::Error) -> ProducerError {
+        ProducerError::Io(err)
+    }
+}
+
+impl From<AuthError> for ProducerError {
+    fn from(err: AuthError) -> ProducerError {
+        ProducerError::Auth(err)
+    }
+}
+
+impl From<SerializationError> for ProducerError {
+    fn from(err: SerializationError) -> ProducerError {
+        ProducerError::Serialization(err)
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use futures::Sink;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{DeliveryResult, Producer, ProducerRecord};
+
+// the producer is moved into the running operation and handed back once it finishes
+type PendingOperation = Pin<Box<dyn Future<Output = (Producer, DeliveryResult)> + Send>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Push,
+    Flush,
+    Close,
+}
+
+// a connected producer as a Sink, so stream.forward(sink) pushes every record and flushes at the end
+// one push runs at a time, under the Block policy poll_ready stays pending while it waits for buffer memory or queue room
+pub struct ProducerSink {
+    producer: Option<Producer>,
+    pending: Option<(Operation, PendingOperation)>,
+    flush_timeout: Duration,
+}
+
+impl ProducerSink {
+    // flush_timeout bounds poll_flush and poll_close, the same way it bounds Producer::flush and Producer::close
+    pub fn new(producer: Producer, flush_timeout: Duration) -> ProducerSink {
+        ProducerSink {
+            producer: Some(producer),
+            pending: None,
+            flush_timeout,
+        }
+    }
+
+    // None while a push, flush or close is still running
+    pub fn into_inner(self) -> Option<Producer> {
+        self.producer
+    }
+
+    // the producer is only missing while an operation runs, which means the caller skipped poll_ready
+    fn start<F, Fut>(&mut self, operation: Operation, run: F) -> DeliveryResult
+    where
+        F: FnOnce(Producer) -> Fut,
+        Fut: Future<Output = (Producer, DeliveryResult)> + Send + 'static,
+    {
+        let producer = self.producer.take().ok_or(ProducerError::SinkNotReady)?;
+        self.pending = Some((operation, Box::pin(run(producer))));
+        Ok(())
+    }
+
+    // drives whatever is running to the end and takes the producer back
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<DeliveryResult> {
+        let (_, pending) = match self.pending.as_mut() {
+            Some(pending) => pending,
+            None => {
+                return Poll::Ready(Ok(()));
+            }
+        };
+
+        let (producer, result) = ready!(pending.as_mut().poll(cx));
+        self.pending = None;
+        self.producer = Some(producer);
+
+        Poll::Ready(result)
+    }
+
+    fn is_running(&self, operation: Operation) -> bool {
+        matches!(&self.pending, Some((running, _)) if *running == operation)
+    }
+}
+
+// the running operation is a boxed future, so only its kind is shown
+impl fmt::Debug for ProducerSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProducerSink")
+            .field("producer", &self.producer)
+            .field("pending", &self.pending.as_ref().map(|(operation, _)| *operation))
+            .field("flush_timeout", &self.flush_timeout)
+            .finish()
+    }
+}
+
+impl Sink<ProducerRecord> for ProducerSink {
+    type Error = ProducerError;
+
+    // a push that failed fails here, which ends a forward with its error
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), ProducerError>> {
+        self.get_mut().poll_pending(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, record: ProducerRecord) -> Result<(), ProducerError> {
+        self.get_mut().start(Operation::Push, |mut producer| async move {
+            let result = producer.push_record(record).await;
+            (producer, result)
+        })
+    }
+
+    // waits until every record pushed so far is acked, not only queued
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), ProducerError>> {
+        let this = self.get_mut();
+
+        if !this.is_running(Operation::Flush) {
+            ready!(this.poll_pending(cx))?;
+
+            let timeout = this.flush_timeout;
+            this.start(Operation::Flush, |mut producer| async move {
+                let result = producer.flush(timeout).await;
+                (producer, result)
+            })?;
+        }
+
+        this.poll_pending(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), ProducerError>> {
+        let this = self.get_mut();
+
+        if !this.is_running(Operation::Close) {
+            ready!(this.poll_pending(cx))?;
+
+            let timeout = this.flush_timeout;
+            this.start(Operation::Close, |mut producer| async move {
+                let result = producer.close(timeout).await;
+                (producer, result)
+            })?;
+        }
+
+        this.poll_pending(cx)
+    }
+}
@@ -1,12 +1,28 @@
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+use std::time::{Duration, Instant};
+use bytes::Bytes;
 use dashmap::DashMap;
 use lazy_static::lazy_static;
 use tokio::io::{ReadHalf, WriteHalf};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::Sender;
-use tokio::sync::RwLock;
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedSender};
+use tokio::sync::{oneshot, watch, Mutex, Notify, OwnedSemaphorePermit, RwLock, Semaphore};
+use tokio::task::JoinHandle;
+use crate::brahmaputra::byte_buffers::concrete_functions::api_versions::ApiVersionRange;
+use crate::brahmaputra::byte_buffers::concrete_functions::authentication::Authenticator;
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::compression::CompressionType;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::{BackpressurePolicy, TransactionState};
+use crate::brahmaputra::byte_buffers::concrete_functions::interceptors::ProducerInterceptor;
+use crate::brahmaputra::byte_buffers::concrete_functions::metrics::ProducerMetrics;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+use crate::brahmaputra::byte_buffers::concrete_functions::rate_limiter::{ProducerRateLimits, RateLimit};
+use crate::brahmaputra::byte_buffers::concrete_functions::spill_journal::SpillJournal;
+use crate::brahmaputra::byte_buffers::concrete_functions::tls::{TlsConfig, TlsSettings};
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct Producer {
     pub max_buffer_size: Option<u64>,
     pub servers: String,
@@ -22,11 +38,328 @@ pub struct Producer {
     pub reconnect_backoff_max_ms: Option<u64>,
     pub socket_keepalive_enable: Option<bool>,
     pub pool: Option<i32>,
+    pub enable_idempotence: Option<bool>,
+    pub transactional_id: Option<String>,
+    pub transaction_timeout_ms: Option<u64>,
+    pub heartbeat_interval_ms: Option<u64>,
+    pub heartbeat_timeout_ms: Option<u64>,
+    pub tls: Option<TlsConfig>,
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    pub backpressure_policy: Option<BackpressurePolicy>,
+    pub buffer_full_timeout_ms: Option<u64>,
+    pub buffer_memory_bytes: Option<u64>,
+    pub spill_dir: Option<String>,
+    pub spill_segment_bytes: Option<u64>,
+    pub spill_max_bytes: Option<u64>,
+    pub max_messages_per_sec: Option<u64>,
+    pub max_bytes_per_sec: Option<u64>,
+    pub topic_rate_limits: Option<HashMap<String, RateLimit>>,
+    pub max_in_flight_per_connection: Option<u64>,
+    pub interceptors: Vec<Arc<dyn ProducerInterceptor>>,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct ProducerIdentity {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+}
+
+// a cloneable producer that pushes straight into the queue, clones share the sender and never take a global lock
+// it is taken from a connected producer and fails with NotConnected once that producer closes or reconnects
+#[derive(Debug, Clone)]
+pub struct ProducerHandle {
+    pub(crate) producer: Arc<Producer>,
+    pub(crate) sender: Sender<QueuedMessage>,
+    pub(crate) budget: Arc<Semaphore>,
+    pub(crate) limits: Option<Arc<ProducerRateLimits>>,
+    pub(crate) identity: Option<ProducerIdentity>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u8,
+    pub retry_backoff_ms: u64,
+    pub retry_backoff_max_ms: u64,
+}
+
+// everything a background task needs to open, watch and reopen pooled connections
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    pub servers: String,
+    pub pool_size: i32,
+    pub reconnect_backoff_ms: u64,
+    pub reconnect_backoff_max_ms: u64,
+    pub heartbeat_interval_ms: u64,
+    pub heartbeat_timeout_ms: u64,
+    pub retry_policy: RetryPolicy,
+    pub max_in_flight_per_connection: usize,
+    pub writer_queue_size: usize,
+    pub tls: Option<TlsSettings>,
+    pub authenticator: Option<Arc<dyn Authenticator>>,
+    pub interceptors: Vec<Arc<dyn ProducerInterceptor>>,
+}
+
+// a message before it is encoded, headers need a broker that accepts v3 frames
+#[derive(Debug, Clone, Default)]
+pub struct ProducerRecord {
+    pub topic: String,
+    pub key: String,
+    pub payload: Bytes,
+    pub headers: Vec<(String, Vec<u8>)>,
+}
+
+impl ProducerRecord {
+    pub fn new(topic: String, key: String, payload: Bytes) -> ProducerRecord {
+        ProducerRecord {
+            topic,
+            key,
+            payload,
+            headers: Vec::new(),
+        }
+    }
+}
+
+// how a message ended, every message gets exactly one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    // the broker stored it
+    Acked,
+    // written with acks 0, the broker never answers
+    Written,
+    // the broker rejected it for good, or its retries ran out
+    Rejected(BrokerError),
+    // the backpressure policy dropped it to make room
+    Dropped,
+    // it never reached the broker, or its connection died before the ack and the frame was not kept
+    Failed,
+}
+
+// what became of a message, after any retries
+#[derive(Debug, Clone)]
+pub struct DeliveryReport {
+    pub topic: String,
+    pub partition: i32,
+    pub key: String,
+    pub unique_key: String,
+    pub outcome: DeliveryOutcome,
+    // what the broker said, or why the producer gave up on the message
+    pub error_msg: String,
+    // time since the first write, None for frames that were never written
+    pub latency: Option<Duration>,
+}
+
+impl DeliveryReport {
+    pub fn new(topic: &str, partition: u32, key: &str, unique_key: &str, outcome: DeliveryOutcome, error_msg: String) -> DeliveryReport {
+        DeliveryReport {
+            topic: topic.to_string(),
+            partition: partition as i32,
+            key: key.to_string(),
+            unique_key: unique_key.to_string(),
+            outcome,
+            error_msg,
+            latency: first_written_at.get(unique_key).map(|written_at| written_at.elapsed()),
+        }
+    }
+
+    pub fn is_delivered(&self) -> bool {
+        matches!(self.outcome, DeliveryOutcome::Acked | DeliveryOutcome::Written)
+    }
+
+    pub fn broker_error(&self) -> Option<BrokerError> {
+        match self.outcome {
+            DeliveryOutcome::Rejected(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// an encoded frame as a header and the payload that ends it, the payload is written from its own buffer
+#[derive(Debug, Clone, Default)]
+pub struct EncodedFrame {
+    pub header: Bytes,
+    pub payload: Bytes,
+}
+
+impl EncodedFrame {
+    pub fn len(&self) -> usize {
+        self.header.len() + self.payload.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the frame in one buffer, for the spill journal
+    pub fn to_vec(&self) -> Vec<u8> {
+        [self.header.as_ref(), self.payload.as_ref()].concat()
+    }
+}
+
+// frames built in one piece, control frames and frames read back from disk
+impl From<Vec<u8>> for EncodedFrame {
+    fn from(frame: Vec<u8>) -> EncodedFrame {
+        EncodedFrame {
+            header: Bytes::from(frame),
+            payload: Bytes::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct InFlightMessage {
+    pub topic: String,
+    pub partition: u32,
+    pub key: String,
+    // -1 for frames without a sequence, they go again in the order they were created
+    pub sequence: i32,
+    pub frame: EncodedFrame,
+    pub attempts: u8,
+    // buffer memory held until the frame is acked
+    pub permit: Option<OwnedSemaphorePermit>,
+    pub created_at: Instant,
+}
+
+// an encoded message waiting in the channel, the key lets a dropped message be settled
+#[derive(Debug)]
+pub struct QueuedMessage {
+    pub unique_key: String,
+    pub topic: String,
+    pub partition: u32,
+    pub key: String,
+    pub frame: EncodedFrame,
+    // buffer memory held until the frame is written, unless it moved to the in flight frame
+    pub permit: Option<OwnedSemaphorePermit>,
+}
+
+impl QueuedMessage {
+    pub(crate) fn report(&self, outcome: DeliveryOutcome, error_msg: String) -> DeliveryReport {
+        DeliveryReport::new(&self.topic, self.partition, &self.key, &self.unique_key, outcome, error_msg)
+    }
+}
+
+// a frame that skips the message queue, retries go back to the connection their partition is pinned to
+#[derive(Debug)]
+pub struct ControlFrame {
+    pub frame: EncodedFrame,
+    pub retry: Option<RetryRoute>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryRoute {
+    pub topic: String,
+    pub partition: u32,
+    pub key: String,
+    pub unique_key: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Message,
+    // a message sent again, it was counted as sent the first time
+    Retry,
+    // not tied to a partition, any live connection can write it
+    Control,
+}
+
+// a frame handed to the writer of the connection it goes out on
+#[derive(Debug)]
+pub struct OutgoingFrame {
+    pub topic: String,
+    pub partition: u32,
+    pub key: String,
+    pub unique_key: String,
+    pub frame: EncodedFrame,
+    // buffer memory held until the frame is written, unless it moved to the in flight frame
+    pub permit: Option<OwnedSemaphorePermit>,
+    pub kind: FrameKind,
+    // lets drop oldest find the message that waited longest across the connections
+    pub queued_at: Instant,
+}
+
+impl OutgoingFrame {
+    pub(crate) fn report(&self, outcome: DeliveryOutcome, error_msg: String) -> DeliveryReport {
+        DeliveryReport::new(&self.topic, self.partition, &self.key, &self.unique_key, outcome, error_msg)
+    }
+}
+
+// a written frame's place in its connection's window, with what its report needs should the connection die before the ack
+#[derive(Debug)]
+pub struct InFlightSlot {
+    pub conn_number: i32,
+    pub permit: OwnedSemaphorePermit,
+    pub topic: String,
+    pub partition: u32,
+    pub key: String,
+}
+
+// frames waiting for the writer of one pooled connection, in the order they go out
+#[derive(Debug)]
+pub struct WriterQueue {
+    pub frames: StdMutex<VecDeque<OutgoingFrame>>,
+    // messages wait in the dispatcher once this many frames are queued, retries and control frames never do
+    pub capacity: usize,
+    // frames arrived, an ack freed a slot or the connection came back or went down
+    pub waker: Notify,
+    // frames left the queue, the dispatcher waits on it while the queue is full
+    pub room: Notify,
+}
+
+// error code and error message the broker sent back for a control request
+pub type ControlResponse = (i32, String);
+
+// final outcome of a message someone is waiting on
+pub type DeliveryResult = Result<(), ProducerError>;
+
+pub type SharedSender = Arc<RwLock<Option<Sender<QueuedMessage>>>>;
+pub type SharedReceiver = Arc<Mutex<Option<Receiver<QueuedMessage>>>>;
+pub type SharedControlSender = Arc<RwLock<Option<UnboundedSender<ControlFrame>>>>;
+pub type SocketWriter = Arc<RwLock<Option<WriteHalf<ProducerStream>>>>;
+pub type SocketReader = Arc<RwLock<Option<ReadHalf<ProducerStream>>>>;
+
 lazy_static! {
-    pub static ref ChannelWriter: Arc<RwLock<Option<Sender<Box<Vec<u8>>>>>> = Arc::new(RwLock::new(None));
-    pub static ref pool_socket_writer: DashMap<i32, Arc<RwLock<Option<WriteHalf<TcpStream>>>>> = DashMap::with_shard_amount(32);
-    pub static ref pool_socket_reader: DashMap<i32, Arc<RwLock<Option<ReadHalf<TcpStream>>>>> = DashMap::with_shard_amount(32);
+    pub static ref ChannelWriter: SharedSender = Arc::new(RwLock::new(None));
+    pub static ref ChannelReader: SharedReceiver = Arc::new(Mutex::new(None));
+    pub static ref ControlChannelWriter: SharedControlSender = Arc::new(RwLock::new(None));
+    pub static ref pool_socket_writer: DashMap<i32, SocketWriter> = DashMap::with_shard_amount(32);
+    pub static ref pool_socket_reader: DashMap<i32, SocketReader> = DashMap::with_shard_amount(32);
+    pub static ref socket_reader_tasks: DashMap<i32, JoinHandle<()>> = DashMap::with_shard_amount(32);
+    pub static ref connection_last_seen: DashMap<i32, Instant> = DashMap::with_shard_amount(32);
+    pub static ref reconnecting_connections: DashMap<i32, ()> = DashMap::with_shard_amount(32);
+    pub static ref connection_session_expiry: DashMap<i32, Instant> = DashMap::with_shard_amount(32);
     pub static ref socket_current_conn: Arc<RwLock<Option<i32>>> = Arc::new(RwLock::new(Some(0)));
+    pub static ref producer_identity: Arc<RwLock<Option<ProducerIdentity>>> = Arc::new(RwLock::new(None));
+    pub static ref partition_sequences: DashMap<(String, u32), i32> = DashMap::with_shard_amount(32);
+    pub static ref in_flight_messages: DashMap<String, InFlightMessage> = DashMap::with_shard_amount(32);
+    pub static ref pending_requests: DashMap<String, oneshot::Sender<ControlResponse>> = DashMap::with_shard_amount(32);
+    pub static ref delivery_waiters: DashMap<String, oneshot::Sender<DeliveryResult>> = DashMap::with_shard_amount(32);
+    pub static ref transaction_state: Arc<RwLock<TransactionState>> = Arc::new(RwLock::new(TransactionState::Uninitialized));
+    pub static ref transaction_partitions: DashMap<(String, u32), ()> = DashMap::with_shard_amount(32);
+    pub static ref broker_api_versions: DashMap<i32, ApiVersionRange> = DashMap::with_shard_amount(32);
+    pub static ref producer_tasks: DashMap<String, JoinHandle<()>> = DashMap::with_shard_amount(32);
+    // parsed from compression_type once per connect instead of on every encode
+    pub static ref producer_compression: StdRwLock<CompressionType> = StdRwLock::new(CompressionType::None);
+    pub static ref producer_closed: AtomicBool = AtomicBool::new(false);
+    // set from connect until close, a second producer connecting would take over the state above
+    pub static ref producer_live: AtomicBool = AtomicBool::new(false);
+    pub static ref queued_frames: AtomicUsize = AtomicUsize::new(0);
+    pub static ref unacked_messages: AtomicUsize = AtomicUsize::new(0);
+    pub static ref flush_notify: Notify = Notify::new();
+    pub static ref dropped_messages: AtomicU64 = AtomicU64::new(0);
+    pub static ref buffer_budget: Arc<RwLock<Option<Arc<Semaphore>>>> = Arc::new(RwLock::new(None));
+    pub static ref producer_metrics: ProducerMetrics = ProducerMetrics::default();
+    pub static ref spill_journal: StdMutex<Option<SpillJournal>> = StdMutex::new(None);
+    pub static ref spill_notify: Notify = Notify::new();
+    // frames appended to the spill journal that are synced to disk, see spawn_spill_sync
+    pub static ref spill_synced: watch::Sender<u64> = watch::channel(0).0;
+    pub static ref spill_sync_notify: Notify = Notify::new();
+    pub static ref rate_limits: Arc<RwLock<Option<Arc<ProducerRateLimits>>>> = Arc::new(RwLock::new(None));
+    pub static ref connection_throttled_until: DashMap<i32, Instant> = DashMap::with_shard_amount(32);
+    pub static ref connection_windows: DashMap<i32, Arc<Semaphore>> = DashMap::with_shard_amount(32);
+    pub static ref in_flight_slots: DashMap<String, InFlightSlot> = DashMap::with_shard_amount(32);
+    // when each message was first handed to a connection, kept until its final outcome
+    pub static ref first_written_at: DashMap<String, Instant> = DashMap::with_shard_amount(32);
+    pub static ref writer_queues: DashMap<i32, Arc<WriterQueue>> = DashMap::with_shard_amount(32);
 }
\ No newline at end of file
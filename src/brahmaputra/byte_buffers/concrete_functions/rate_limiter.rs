@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+// limits for one topic, unset means unlimited
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimit {
+    pub messages_per_sec: Option<u64>,
+    pub bytes_per_sec: Option<u64>,
+}
+
+// refills continuously and holds at most one second worth of tokens as burst
+// reserving more than is there leaves a debt, so a caller learns how long to wait and later callers queue up behind it
+#[derive(Debug)]
+pub struct TokenBucket {
+    rate: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(per_sec: u64) -> TokenBucket {
+        TokenBucket {
+            rate: per_sec as f64,
+            state: Mutex::new(BucketState {
+                tokens: per_sec as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    // takes the tokens straight away and returns how long to wait before using them
+    pub fn reserve(&self, amount: u64) -> Duration {
+        let mut state = self.refill();
+        state.tokens -= amount as f64;
+
+        if state.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-state.tokens / self.rate)
+        }
+    }
+
+    // takes the tokens only when they are there, more than a second worth only needs a full bucket
+    pub fn try_reserve(&self, amount: u64) -> bool {
+        let mut state = self.refill();
+        if state.tokens < (amount as f64).min(self.rate) {
+            return false;
+        }
+
+        state.tokens -= amount as f64;
+        true
+    }
+
+    // hands back tokens of a reservation that was not used
+    pub fn release(&self, amount: u64) {
+        let mut state = self.refill();
+        state.tokens = (state.tokens + amount as f64).min(self.rate);
+    }
+
+    fn refill(&self) -> std::sync::MutexGuard<'_, BucketState> {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.rate).min(self.rate);
+        state.refilled_at = now;
+
+        state
+    }
+}
+
+// a message and a byte bucket, either can be missing
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    messages: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: RateLimit) -> RateLimiter {
+        RateLimiter {
+            messages: limit.messages_per_sec.map(TokenBucket::new),
+            bytes: limit.bytes_per_sec.map(TokenBucket::new),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.messages.is_none() && self.bytes.is_none()
+    }
+
+    pub fn reserve(&self, bytes: u64) -> Duration {
+        let messages_wait = self.messages.as_ref().map(|bucket| bucket.reserve(1)).unwrap_or_default();
+        let bytes_wait = self.bytes.as_ref().map(|bucket| bucket.reserve(bytes)).unwrap_or_default();
+        messages_wait.max(bytes_wait)
+    }
+
+    // both buckets or neither, so a refused message does not use up the other limit
+    pub fn try_reserve(&self, bytes: u64) -> bool {
+        if let Some(messages) = &self.messages {
+            if !messages.try_reserve(1) {
+                return false;
+            }
+        }
+
+        if let Some(bucket) = &self.bytes {
+            if !bucket.try_reserve(bytes) {
+                if let Some(messages) = &self.messages {
+                    messages.release(1);
+                }
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn release(&self, bytes: u64) {
+        if let Some(messages) = &self.messages {
+            messages.release(1);
+        }
+        if let Some(bucket) = &self.bytes {
+            bucket.release(bytes);
+        }
+    }
+}
+
+// the producer wide limit and one per limited topic, a message has to pass both
+#[derive(Debug, Default)]
+pub struct ProducerRateLimits {
+    producer: RateLimiter,
+    topics: HashMap<String, RateLimiter>,
+}
+
+impl ProducerRateLimits {
+    pub fn new(producer: RateLimit, topics: &HashMap<String, RateLimit>) -> ProducerRateLimits {
+        ProducerRateLimits {
+            producer: RateLimiter::new(producer),
+            topics: topics.iter().map(|(topic, limit)| (topic.to_string(), RateLimiter::new(*limit))).collect(),
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.producer.is_unlimited() && self.topics.values().all(RateLimiter::is_unlimited)
+    }
+
+    // how long the message has to wait before it may be queued
+    pub fn reserve(&self, topic: &str, bytes: u64) -> Duration {
+        let producer_wait = self.producer.reserve(bytes);
+        let topic_wait = self.topics.get(topic).map(|limiter| limiter.reserve(bytes)).unwrap_or_default();
+        producer_wait.max(topic_wait)
+    }
+
+    // false when the message would have to wait
+    pub fn try_reserve(&self, topic: &str, bytes: u64) -> bool {
+        if !self.producer.try_reserve(bytes) {
+            return false;
+        }
+
+        if let Some(limiter) = self.topics.get(topic) {
+            if !limiter.try_reserve(bytes) {
+                self.producer.release(bytes);
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // hands back a reservation for a message that never made it into the queue
+    pub fn release(&self, topic: &str, bytes: u64) {
+        self.producer.release(bytes);
+        if let Some(limiter) = self.topics.get(topic) {
+            limiter.release(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use super::{ProducerRateLimits, RateLimit, TokenBucket};
+
+    #[test]
+    fn bucket_allows_a_burst_then_asks_to_wait() {
+        let bucket = TokenBucket::new(10);
+
+        for _ in 0..10 {
+            assert_eq!(bucket.reserve(1), Duration::ZERO);
+        }
+
+        // one token short, a tenth of a second at 10 per second
+        let wait = bucket.reserve(1);
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100), "{:?}", wait);
+        assert!(!bucket.try_reserve(1));
+    }
+
+    #[test]
+    fn topic_limit_applies_on_top_of_the_producer_limit() {
+        let mut topics = HashMap::new();
+        topics.insert("slow".to_string(), RateLimit { messages_per_sec: Some(1), bytes_per_sec: None });
+        let limits = ProducerRateLimits::new(RateLimit { messages_per_sec: None, bytes_per_sec: Some(1000) }, &topics);
+
+        assert!(limits.try_reserve("slow", 100));
+        assert!(!limits.try_reserve("slow", 100));
+        assert!(limits.try_reserve("fast", 100));
+
+        // the refused message did not use up bytes, 800 are left for the producer
+        assert!(!limits.try_reserve("fast", 900));
+        assert!(limits.try_reserve("fast", 800));
+        assert!(!limits.try_reserve("fast", 1));
+    }
+
+    #[test]
+    fn released_reservations_can_be_taken_again() {
+        let mut topics = HashMap::new();
+        topics.insert("slow".to_string(), RateLimit { messages_per_sec: Some(1), bytes_per_sec: None });
+        let limits = ProducerRateLimits::new(RateLimit { messages_per_sec: Some(2), bytes_per_sec: None }, &topics);
+
+        assert!(limits.try_reserve("slow", 100));
+        assert!(!limits.try_reserve("slow", 100));
+
+        // the message was not queued after all, both the producer and the topic bucket get it back
+        limits.release("slow", 100);
+        assert!(limits.try_reserve("slow", 100));
+        assert!(limits.try_reserve("fast", 100));
+        assert!(!limits.try_reserve("fast", 100));
+    }
+}
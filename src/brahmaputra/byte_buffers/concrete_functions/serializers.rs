@@ -0,0 +1,222 @@
+use std::fmt;
+use std::string::FromUtf8Error;
+use serde::Serialize;
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+
+#[derive(Debug)]
+pub enum SerializationError {
+    Json(serde_json::Error),
+    // keys travel as strings in the frame, so their bytes have to be utf-8
+    InvalidKey(FromUtf8Error),
+    // for serializers written outside this crate
+    Custom(String),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Json(err) => write!(f, "json: {}", err),
+            SerializationError::InvalidKey(err) => write!(f, "key is not valid utf-8: {}", err),
+            SerializationError::Custom(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializationError::Json(err) => Some(err),
+            SerializationError::InvalidKey(err) => Some(err),
+            SerializationError::Custom(_) => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for SerializationError {
+    fn from(err: serde_json::Error) -> SerializationError {
+        SerializationError::Json(err)
+    }
+}
+
+// turns a key or a value into the bytes that go into the frame
+pub trait Serializer<T>: Send + Sync {
+    fn serialize(&self, topic: &str, value: &T) -> Result<Vec<u8>, SerializationError>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonSerializer;
+
+impl<T: Serialize> Serializer<T> for JsonSerializer {
+    fn serialize(&self, _topic: &str, value: &T) -> Result<Vec<u8>, SerializationError> {
+        Ok(serde_json::to_vec(value)?)
+    }
+}
+
+// the utf-8 bytes of the string as they are, without json quoting
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StringSerializer;
+
+impl<T: AsRef<str>> Serializer<T> for StringSerializer {
+    fn serialize(&self, _topic: &str, value: &T) -> Result<Vec<u8>, SerializationError> {
+        Ok(value.as_ref().as_bytes().to_vec())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BytesSerializer;
+
+impl<T: AsRef<[u8]>> Serializer<T> for BytesSerializer {
+    fn serialize(&self, _topic: &str, value: &T) -> Result<Vec<u8>, SerializationError> {
+        Ok(value.as_ref().to_vec())
+    }
+}
+
+// types that write themselves with the ByteBuff primitives, so consumers can read them back with the matching getters
+pub trait ByteBuffEncode {
+    fn encode(&self, bb: &mut ByteBuff);
+}
+
+impl ByteBuffEncode for i16 {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_short(*self);
+    }
+}
+
+impl ByteBuffEncode for i32 {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_int(*self);
+    }
+}
+
+impl ByteBuffEncode for i64 {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_long(*self);
+    }
+}
+
+impl ByteBuffEncode for f64 {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_float(*self);
+    }
+}
+
+impl ByteBuffEncode for bool {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_bool(*self);
+    }
+}
+
+impl ByteBuffEncode for String {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put_string(self.to_string());
+    }
+}
+
+impl ByteBuffEncode for Vec<u8> {
+    fn encode(&self, bb: &mut ByteBuff) {
+        bb.put(self.to_vec());
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByteBuffSerializer;
+
+impl<T: ByteBuffEncode> Serializer<T> for ByteBuffSerializer {
+    fn serialize(&self, _topic: &str, value: &T) -> Result<Vec<u8>, SerializationError> {
+        let mut bb = ByteBuff {
+            multiplier: 10000.0,
+            endian: "big".to_string(),
+            ..Default::default()
+        };
+
+        // into big endian format
+        bb.init("big".to_string());
+
+        value.encode(&mut bb);
+
+        Ok(bb.to_array())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::error::Error;
+    use serde::{Deserialize, Serialize};
+    use super::{ByteBuffSerializer, BytesSerializer, JsonSerializer, SerializationError, Serializer, StringSerializer};
+    use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+    use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+    use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+    use crate::brahmaputra::byte_buffers::concrete_functions::typed_producer::TypedProducer;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        symbol: String,
+        price: f64,
+        tags: Vec<String>,
+    }
+
+    fn read_back(bytes: Vec<u8>) -> ByteBuff {
+        let mut bb = ByteBuff::default();
+        bb.init("big".to_string());
+        bb.wrap(bytes);
+        bb
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let order = Order {
+            id: 42,
+            symbol: "NIFTY \"50\"".to_string(),
+            price: 101.25,
+            tags: vec!["buy".to_string(), "ioc".to_string()],
+        };
+
+        let bytes = JsonSerializer.serialize("orders", &order).unwrap();
+        assert_eq!(serde_json::from_slice::<Order>(&bytes).unwrap(), order);
+    }
+
+    #[test]
+    fn json_fails_for_values_it_cannot_represent() {
+        let mut positions = HashMap::new();
+        positions.insert((1, 2), 3);
+
+        let err = JsonSerializer.serialize("positions", &positions).unwrap_err();
+        assert!(matches!(err, SerializationError::Json(_)));
+        assert!(err.to_string().starts_with("json: "));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn string_and_bytes_are_written_as_they_are() {
+        assert_eq!(StringSerializer.serialize("logs", &"héllo").unwrap(), "héllo".as_bytes().to_vec());
+        assert_eq!(BytesSerializer.serialize("logs", &vec![0u8, 255, 7]).unwrap(), vec![0u8, 255, 7]);
+    }
+
+    #[test]
+    fn byte_buff_values_read_back_with_the_matching_getters() {
+        assert_eq!(read_back(ByteBuffSerializer.serialize("t", &-12i16).unwrap()).get_short(), -12);
+        assert_eq!(read_back(ByteBuffSerializer.serialize("t", &-123456i32).unwrap()).get_int(), -123456);
+        assert_eq!(read_back(ByteBuffSerializer.serialize("t", &i64::MIN).unwrap()).get_long(), i64::MIN);
+        assert_eq!(read_back(ByteBuffSerializer.serialize("t", &12.5f64).unwrap()).get_float(), 12.5);
+        assert!(read_back(ByteBuffSerializer.serialize("t", &true).unwrap()).get_bool());
+        assert_eq!(read_back(ByteBuffSerializer.serialize("t", &vec![1u8, 2, 3]).unwrap()).get(), vec![1u8, 2, 3]);
+
+        // short and long strings carry different length prefixes
+        for text in ["sudeep".to_string(), "x".repeat(300)] {
+            let mut bb = read_back(ByteBuffSerializer.serialize("t", &text).unwrap());
+            assert_eq!(bb.get_string(), text);
+            assert_eq!(bb.remaining(), 0);
+        }
+    }
+
+    #[test]
+    fn keys_that_are_not_utf8_are_rejected_before_queueing() {
+        let mut producer: TypedProducer<Vec<u8>, String, BytesSerializer, StringSerializer> =
+            TypedProducer::with_serializers(Producer::default(), BytesSerializer, StringSerializer);
+
+        let err = producer.try_push("orders".to_string(), &vec![0xff, 0xfe], &"value".to_string()).unwrap_err();
+        assert!(matches!(err, ProducerError::Serialization(SerializationError::InvalidKey(_))));
+    }
+}
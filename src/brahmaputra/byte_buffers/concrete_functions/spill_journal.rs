@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+const SEGMENT_EXTENSION: &str = "seg";
+
+// a frame read back from disk, with what the producer needs to send and settle it again
+#[derive(Debug)]
+pub struct SpilledFrame {
+    pub unique_key: String,
+    pub topic: String,
+    pub frame: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Segment {
+    bytes: u64,
+    // frames in the segment without a final ack
+    outstanding: usize,
+}
+
+// append only segment files holding encoded frames while they cannot be kept in memory
+// a record is the unique key, the topic and the frame, each prefixed with its big endian length
+// segments are deleted once every frame in them has been read back and acked
+// appends only reach the page cache, take_unsynced hands out the files to sync so the fsync runs without the lock
+#[derive(Debug)]
+pub struct SpillJournal {
+    dir: PathBuf,
+    segment_bytes: u64,
+    max_bytes: u64,
+    total_bytes: u64,
+    segments: BTreeMap<u64, Segment>,
+    next_segment: u64,
+    writer: Option<(u64, File)>,
+    // handles of the segments written since the last take_unsynced, and whether the writer is one of them
+    unsynced: Vec<File>,
+    writer_unsynced: bool,
+    appended: u64,
+    // segments before read_segment have been read back completely
+    read_segment: u64,
+    read_offset: u64,
+    reader: Option<BufReader<File>>,
+    // segment of every frame without a final ack
+    keys: HashMap<String, u64>,
+    unreplayed: usize,
+}
+
+impl SpillJournal {
+    // frames left behind by an earlier run are read back first, new frames always go to a fresh segment
+    pub fn open<P: AsRef<Path>>(dir: P, segment_bytes: u64, max_bytes: u64) -> io::Result<SpillJournal> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let mut journal = SpillJournal {
+            dir,
+            segment_bytes,
+            max_bytes,
+            total_bytes: 0,
+            segments: BTreeMap::new(),
+            next_segment: 0,
+            writer: None,
+            unsynced: Vec::new(),
+            writer_unsynced: false,
+            appended: 0,
+            read_segment: 0,
+            read_offset: 0,
+            reader: None,
+            keys: HashMap::new(),
+            unreplayed: 0,
+        };
+
+        let mut ids = Vec::new();
+        for entry in fs::read_dir(&journal.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some(SEGMENT_EXTENSION) {
+                continue;
+            }
+            if let Some(id) = path.file_stem().and_then(|stem| stem.to_str()).and_then(|stem| stem.parse::<u64>().ok()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+
+        for id in ids {
+            journal.recover_segment(id)?;
+            journal.next_segment = id + 1;
+        }
+        journal.read_segment = journal.segments.keys().next().copied().unwrap_or(0);
+
+        Ok(journal)
+    }
+
+    // frames written but not handed back to the producer yet, including one that was read and is being queued
+    pub fn unreplayed(&self) -> usize {
+        self.unreplayed
+    }
+
+    // the frame returned by next_frame is back in the queue
+    pub fn replayed(&mut self) {
+        self.unreplayed = self.unreplayed.saturating_sub(1);
+    }
+
+    // bytes on disk, including frames that were read back and wait for their ack
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    // false when the journal would grow past max_bytes
+    pub fn append(&mut self, unique_key: &str, topic: &str, frame: &[u8]) -> io::Result<bool> {
+        let record = encode_record(unique_key, topic, frame);
+        let length = record.len() as u64;
+
+        if self.total_bytes + length > self.max_bytes {
+            return Ok(false);
+        }
+
+        let current = self.writer.as_ref().and_then(|(id, _)| self.segments.get(id).map(|segment| segment.bytes));
+        if !matches!(current, Some(bytes) if bytes == 0 || bytes + length <= self.segment_bytes) {
+            self.roll_segment()?;
+        }
+
+        let (id, file) = match self.writer.as_mut() {
+            Some((id, file)) => (*id, file),
+            None => {
+                return Err(io::Error::new(ErrorKind::NotFound, "spill journal has no open segment"));
+            }
+        };
+        let segment = self.segments.entry(id).or_default();
+
+        if let Err(err) = file.write_all(&record) {
+            // cutting off a partial record so the segment can still be read back
+            let _ = file.set_len(segment.bytes);
+            return Err(err);
+        }
+
+        if !self.writer_unsynced {
+            match file.try_clone() {
+                Ok(handle) => self.unsynced.push(handle),
+                Err(err) => {
+                    let _ = file.set_len(segment.bytes);
+                    return Err(err);
+                }
+            }
+            self.writer_unsynced = true;
+        }
+
+        segment.bytes += length;
+        segment.outstanding += 1;
+        self.total_bytes += length;
+        self.keys.insert(unique_key.to_string(), id);
+        self.unreplayed += 1;
+        self.appended += 1;
+
+        Ok(true)
+    }
+
+    // frames appended since the journal was opened
+    pub fn appended(&self) -> u64 {
+        self.appended
+    }
+
+    // the files to sync for every frame appended so far, one sync covers all frames written since the last call
+    pub fn take_unsynced(&mut self) -> (u64, Vec<File>) {
+        self.writer_unsynced = false;
+        (self.appended, std::mem::take(&mut self.unsynced))
+    }
+
+    // the next frame in the order it was written, None once everything written so far has been read back
+    pub fn next_frame(&mut self) -> io::Result<Option<SpilledFrame>> {
+        loop {
+            let (id, bytes) = match self.segments.range(self.read_segment..).next() {
+                Some((id, segment)) => (*id, segment.bytes),
+                None => {
+                    return Ok(None);
+                }
+            };
+
+            if id != self.read_segment {
+                self.read_segment = id;
+                self.read_offset = 0;
+                self.reader = None;
+            }
+
+            if self.read_offset < bytes {
+                let mut reader = match self.reader.take() {
+                    Some(reader) => reader,
+                    None => {
+                        let mut file = File::open(self.segment_path(id))?;
+                        file.seek(SeekFrom::Start(self.read_offset))?;
+                        BufReader::new(file)
+                    }
+                };
+
+                let (spilled, length) = read_record(&mut reader)?;
+                self.reader = Some(reader);
+                self.read_offset += length;
+
+                return Ok(Some(spilled));
+            }
+
+            // the segment being written can still grow
+            if self.writer.as_ref().map(|(writer_id, _)| *writer_id) == Some(id) {
+                return Ok(None);
+            }
+
+            self.read_segment = id + 1;
+            self.read_offset = 0;
+            self.reader = None;
+            self.remove_if_done(id)?;
+        }
+    }
+
+    // the frame got its final ack, unknown keys are ignored
+    pub fn settle(&mut self, unique_key: &str) -> io::Result<()> {
+        let id = match self.keys.remove(unique_key) {
+            Some(id) => id,
+            None => {
+                return Ok(());
+            }
+        };
+
+        if let Some(segment) = self.segments.get_mut(&id) {
+            segment.outstanding = segment.outstanding.saturating_sub(1);
+        }
+
+        self.remove_if_done(id)
+    }
+
+    fn roll_segment(&mut self) -> io::Result<()> {
+        let id = self.next_segment;
+        let file = OpenOptions::new().create_new(true).append(true).open(self.segment_path(id))?;
+
+        self.next_segment += 1;
+        self.segments.insert(id, Segment::default());
+        // the handle of the previous segment stays in unsynced until it was synced
+        self.writer_unsynced = false;
+        let previous = self.writer.replace((id, file)).map(|(previous, _)| previous);
+
+        // the previous segment may already be read back and acked
+        match previous {
+            Some(previous) => self.remove_if_done(previous),
+            None => Ok(()),
+        }
+    }
+
+    fn remove_if_done(&mut self, id: u64) -> io::Result<()> {
+        let done = match self.segments.get(&id) {
+            Some(segment) => {
+                let read_back = id < self.read_segment || (id == self.read_segment && self.read_offset >= segment.bytes);
+                segment.outstanding == 0 && read_back
+            }
+            None => false,
+        };
+        if !done {
+            return Ok(());
+        }
+
+        if self.writer.as_ref().map(|(writer_id, _)| *writer_id) == Some(id) {
+            self.writer = None;
+            self.writer_unsynced = false;
+        }
+        if id == self.read_segment {
+            self.read_segment = id + 1;
+            self.read_offset = 0;
+            self.reader = None;
+        }
+
+        if let Some(segment) = self.segments.remove(&id) {
+            self.total_bytes = self.total_bytes.saturating_sub(segment.bytes);
+        }
+
+        fs::remove_file(self.segment_path(id))
+    }
+
+    // counts the frames of a segment left on disk, a record cut off by a crash is truncated away
+    fn recover_segment(&mut self, id: u64) -> io::Result<()> {
+        let path = self.segment_path(id);
+        let mut reader = BufReader::new(File::open(&path)?);
+
+        let mut segment = Segment::default();
+        loop {
+            match read_record(&mut reader) {
+                Ok((spilled, length)) => {
+                    segment.bytes += length;
+                    segment.outstanding += 1;
+                    self.keys.insert(spilled.unique_key, id);
+                }
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof || err.kind() == ErrorKind::InvalidData => {
+                    let file_bytes = fs::metadata(&path)?.len();
+                    if file_bytes > segment.bytes {
+                        warn!(segment = %path.display(), valid_bytes = segment.bytes, file_bytes, "truncating damaged spill segment");
+                        OpenOptions::new().write(true).open(&path)?.set_len(segment.bytes)?;
+                    }
+                    break;
+                }
+                Err(err) => {
+                    return Err(err);
+                }
+            }
+        }
+
+        if segment.outstanding == 0 {
+            return fs::remove_file(&path);
+        }
+
+        self.total_bytes += segment.bytes;
+        self.unreplayed += segment.outstanding;
+        self.segments.insert(id, segment);
+
+        Ok(())
+    }
+
+    fn segment_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.{}", id, SEGMENT_EXTENSION))
+    }
+}
+
+fn encode_record(unique_key: &str, topic: &str, frame: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(16 + unique_key.len() + topic.len() + frame.len());
+    record.extend_from_slice(&(unique_key.len() as u32).to_be_bytes());
+    record.extend_from_slice(unique_key.as_bytes());
+    record.extend_from_slice(&(topic.len() as u32).to_be_bytes());
+    record.extend_from_slice(topic.as_bytes());
+    record.extend_from_slice(&(frame.len() as u64).to_be_bytes());
+    record.extend_from_slice(frame);
+    record
+}
+
+// returns the record and how many bytes it took on disk
+fn read_record<R: Read>(reader: &mut R) -> io::Result<(SpilledFrame, u64)> {
+    let mut length_buf = [0u8; 4];
+
+    reader.read_exact(&mut length_buf)?;
+    let unique_key = read_string(reader, u32::from_be_bytes(length_buf) as u64)?;
+
+    reader.read_exact(&mut length_buf)?;
+    let topic = read_string(reader, u32::from_be_bytes(length_buf) as u64)?;
+
+    let mut frame_length_buf = [0u8; 8];
+    reader.read_exact(&mut frame_length_buf)?;
+    let frame = read_bytes(reader, u64::from_be_bytes(frame_length_buf))?;
+
+    let length = 16 + unique_key.len() as u64 + topic.len() as u64 + frame.len() as u64;
+    Ok((SpilledFrame { unique_key, topic, frame }, length))
+}
+
+fn read_string<R: Read>(reader: &mut R, length: u64) -> io::Result<String> {
+    String::from_utf8(read_bytes(reader, length)?).map_err(|err| io::Error::new(ErrorKind::InvalidData, err))
+}
+
+// reading through take so a damaged length cannot allocate more than the file holds
+fn read_bytes<R: Read>(reader: &mut R, length: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(length).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < length {
+        return Err(io::Error::new(ErrorKind::UnexpectedEof, "spill record is cut off"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::PathBuf;
+    use uuid::Uuid;
+    use super::SpillJournal;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("brahmaputra-spill-{}", Uuid::new_v4()))
+    }
+
+    fn segment_files(dir: &PathBuf) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn frames_come_back_in_order_and_segments_go_once_acked() {
+        let dir = temp_dir();
+        // records are 34 bytes, so every two frames roll over to a new file
+        let mut journal = SpillJournal::open(&dir, 80, 1 << 20).unwrap();
+
+        for i in 0..5 {
+            assert!(journal.append(&format!("key-{}", i), "orders", format!("frame-{}", i).as_bytes()).unwrap());
+        }
+        assert_eq!(journal.unreplayed(), 5);
+        assert_eq!(segment_files(&dir), 3);
+
+        let mut keys = Vec::new();
+        while let Some(spilled) = journal.next_frame().unwrap() {
+            assert_eq!(spilled.topic, "orders");
+            assert_eq!(spilled.frame, format!("frame-{}", keys.len()).into_bytes());
+            keys.push(spilled.unique_key);
+            journal.replayed();
+        }
+        assert_eq!(keys.len(), 5);
+        assert_eq!(journal.unreplayed(), 0);
+
+        for key in &keys {
+            journal.settle(key).unwrap();
+        }
+        assert_eq!(segment_files(&dir), 0);
+        assert_eq!(journal.total_bytes(), 0);
+
+        // the journal keeps working after its last segment was deleted
+        assert!(journal.append("key-5", "orders", b"frame-5").unwrap());
+        assert_eq!(journal.next_frame().unwrap().unwrap().unique_key, "key-5");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unacked_frames_survive_a_restart_and_a_torn_record_is_dropped() {
+        let dir = temp_dir();
+        let mut journal = SpillJournal::open(&dir, 1 << 20, 1 << 20).unwrap();
+        journal.append("key-0", "orders", b"frame-0").unwrap();
+        journal.append("key-1", "orders", b"frame-1").unwrap();
+
+        // acked before the crash, so only key-1 is expected back
+        let first = journal.next_frame().unwrap().unwrap();
+        journal.replayed();
+        journal.settle(&first.unique_key).unwrap();
+        drop(journal);
+
+        // a record cut off half way through, as a crash during append leaves it
+        let segment = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        OpenOptions::new().append(true).open(&segment).unwrap().write_all(&[0, 0, 0, 5, b'k']).unwrap();
+
+        let mut journal = SpillJournal::open(&dir, 1 << 20, 1 << 20).unwrap();
+        assert_eq!(journal.unreplayed(), 2);
+
+        let frames: Vec<String> = std::iter::from_fn(|| journal.next_frame().unwrap()).map(|spilled| spilled.unique_key).collect();
+        assert_eq!(frames, vec!["key-0".to_string(), "key-1".to_string()]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn one_sync_covers_every_segment_written_since_the_last() {
+        let dir = temp_dir();
+        let mut journal = SpillJournal::open(&dir, 80, 1 << 20).unwrap();
+
+        for i in 0..3 {
+            journal.append(&format!("key-{}", i), "orders", format!("frame-{}", i).as_bytes()).unwrap();
+        }
+        let (appended, files) = journal.take_unsynced();
+        assert_eq!(appended, 3);
+        // the third frame rolled over to a second segment
+        assert_eq!(files.len(), 2);
+        for file in &files {
+            file.sync_data().unwrap();
+        }
+
+        let (appended, files) = journal.take_unsynced();
+        assert_eq!((appended, files.len()), (3, 0));
+
+        journal.append("key-3", "orders", b"frame-3").unwrap();
+        let (appended, files) = journal.take_unsynced();
+        assert_eq!((appended, files.len()), (4, 1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append_refuses_frames_past_max_bytes() {
+        let dir = temp_dir();
+        let mut journal = SpillJournal::open(&dir, 1 << 20, 64).unwrap();
+
+        assert!(journal.append("key-0", "orders", &[0u8; 16]).unwrap());
+        assert!(!journal.append("key-1", "orders", &[0u8; 16]).unwrap());
+        assert_eq!(journal.unreplayed(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
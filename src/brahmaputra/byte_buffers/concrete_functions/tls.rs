@@ -0,0 +1,374 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind};
+use std::sync::Arc;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tracing::warn;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    // PEM bundle of trusted CAs, the webpki roots are used when it is not set
+    pub ca_location: Option<String>,
+    // PEM client certificate and private key, both are needed for mTLS
+    pub certificate_location: Option<String>,
+    pub key_location: Option<String>,
+    // name sent as SNI and checked against the broker certificate, defaults to the host in servers
+    pub server_name: Option<String>,
+    // skips certificate verification entirely, only meant for tests against throwaway brokers
+    pub insecure_skip_verify: bool,
+}
+
+#[derive(Clone)]
+pub struct TlsSettings {
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+    insecure_skip_verify: bool,
+}
+
+impl fmt::Debug for TlsSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TlsSettings")
+            .field("server_name", &self.server_name)
+            .field("insecure_skip_verify", &self.insecure_skip_verify)
+            .finish()
+    }
+}
+
+impl TlsSettings {
+    pub fn from_config(config: &TlsConfig, servers: &str) -> Result<TlsSettings, Error> {
+        let provider = Arc::new(ring::default_provider());
+
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?;
+
+        let builder = if config.insecure_skip_verify {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(InsecureVerifier { provider }))
+        } else {
+            let mut roots = RootCertStore::empty();
+            match &config.ca_location {
+                Some(ca_location) => {
+                    for cert in read_certificates(ca_location)? {
+                        roots.add(cert).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+                    }
+                }
+                None => {
+                    roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+                }
+            }
+            builder.with_root_certificates(roots)
+        };
+
+        let client_config = match (&config.certificate_location, &config.key_location) {
+            (Some(certificate_location), Some(key_location)) => {
+                let certs = read_certificates(certificate_location)?;
+                let key = read_private_key(key_location)?;
+                builder
+                    .with_client_auth_cert(certs, key)
+                    .map_err(|err| Error::new(ErrorKind::InvalidInput, err))?
+            }
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(Error::new(ErrorKind::InvalidInput, "mTLS needs both a certificate and a key"));
+            }
+        };
+
+        let host = match &config.server_name {
+            Some(server_name) => server_name.to_string(),
+            None => host_from_servers(servers),
+        };
+
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|err| Error::new(ErrorKind::InvalidInput, format!("invalid server name {}: {}", host, err)))?;
+
+        Ok(TlsSettings {
+            connector: TlsConnector::from(Arc::new(client_config)),
+            server_name,
+            insecure_skip_verify: config.insecure_skip_verify,
+        })
+    }
+
+    pub async fn connect(&self, stream: TcpStream) -> Result<ProducerStream, Error> {
+        // logged on every connection so the setting cannot quietly survive into production
+        if self.insecure_skip_verify {
+            warn!(server_name = ?self.server_name, "tls certificate verification is disabled, the broker is not authenticated");
+        }
+        let stream = self.connector.connect(self.server_name.clone(), stream).await?;
+        Ok(ProducerStream::Tls(Box::new(stream)))
+    }
+}
+
+fn read_certificates(location: &str) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let mut reader = BufReader::new(File::open(location)?);
+    let certs = rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| Error::new(ErrorKind::InvalidData, format!("failed to read {}: {}", location, err)))?;
+
+    if certs.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidData, format!("no certificates found in {}", location)));
+    }
+
+    Ok(certs)
+}
+
+fn read_private_key(location: &str) -> Result<PrivateKeyDer<'static>, Error> {
+    let mut reader = BufReader::new(File::open(location)?);
+    match rustls_pemfile::private_key(&mut reader)? {
+        Some(key) => Ok(key),
+        None => Err(Error::new(ErrorKind::InvalidData, format!("no private key found in {}", location))),
+    }
+}
+
+fn host_from_servers(servers: &str) -> String {
+    let host = match servers.rsplit_once(':') {
+        Some((host, _)) => host,
+        None => servers,
+    };
+    host.trim_start_matches('[').trim_end_matches(']').to_string()
+}
+
+#[derive(Debug)]
+struct InsecureVerifier {
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for InsecureVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, DnType, IsCa, KeyPair};
+    use rustls::crypto::ring;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{RootCertStore, ServerConfig};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsAcceptor;
+    use uuid::Uuid;
+    use super::{read_certificates, read_private_key, TlsConfig, TlsSettings};
+
+    struct TestPki {
+        dir: PathBuf,
+        ca: Certificate,
+        ca_key: KeyPair,
+    }
+
+    impl TestPki {
+        fn new() -> TestPki {
+            let dir = std::env::temp_dir().join(format!("brahmaputra-tls-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&dir).unwrap();
+
+            let mut params = CertificateParams::new(Vec::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            params.distinguished_name.push(DnType::CommonName, "brahmaputra test ca");
+            let ca_key = KeyPair::generate().unwrap();
+            let ca = params.self_signed(&ca_key).unwrap();
+            std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+
+            TestPki { dir, ca, ca_key }
+        }
+
+        // issues a certificate signed by the test CA and returns the cert and key paths
+        fn issue(&self, name: &str) -> (String, String) {
+            let params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.ca, &self.ca_key).unwrap();
+
+            let cert_path = self.dir.join(format!("{}.pem", name));
+            let key_path = self.dir.join(format!("{}.key", name));
+            std::fs::write(&cert_path, cert.pem()).unwrap();
+            std::fs::write(&key_path, key.serialize_pem()).unwrap();
+
+            (cert_path.to_string_lossy().to_string(), key_path.to_string_lossy().to_string())
+        }
+
+        fn ca_location(&self) -> String {
+            self.dir.join("ca.pem").to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestPki {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // starts a TLS server that echoes back a single length prefixed frame
+    async fn start_echo_server(pki: &TestPki, require_client_cert: bool) -> u16 {
+        let (cert_path, key_path) = pki.issue("localhost");
+        let certs = read_certificates(&cert_path).unwrap();
+        let key = read_private_key(&key_path).unwrap();
+
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .unwrap();
+
+        let builder = if require_client_cert {
+            let mut roots = RootCertStore::empty();
+            roots.add_parsable_certificates(read_certificates(&pki.ca_location()).unwrap());
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider).build().unwrap();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+
+        let acceptor = TlsAcceptor::from(Arc::new(builder.with_single_cert(certs, key).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(_) => {
+                    return;
+                }
+            };
+
+            let mut length_buf = [0u8; 8];
+            stream.read_exact(&mut length_buf).await.unwrap();
+            let mut total_buf = vec![0u8; usize::from_be_bytes(length_buf)];
+            stream.read_exact(&mut total_buf).await.unwrap();
+
+            stream.write_all(&length_buf).await.unwrap();
+            stream.write_all(&total_buf).await.unwrap();
+            stream.flush().await.unwrap();
+        });
+
+        port
+    }
+
+    // writes through the write half and reads the echo through the read half, the same way the pool uses them
+    async fn echo_frame(settings: &TlsSettings, port: u16) -> std::io::Result<Vec<u8>> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).await?;
+        let stream = settings.connect(stream).await?;
+        let (mut read_half, mut write_half) = tokio::io::split(stream);
+
+        let payload = b"hello sudeep".to_vec();
+        write_half.write_all(&(payload.len() as u64).to_be_bytes()).await?;
+        write_half.write_all(&payload).await?;
+        write_half.flush().await?;
+
+        let mut length_buf = [0u8; 8];
+        read_half.read_exact(&mut length_buf).await?;
+        let mut total_buf = vec![0u8; usize::from_be_bytes(length_buf)];
+        read_half.read_exact(&mut total_buf).await?;
+
+        Ok(total_buf)
+    }
+
+    #[tokio::test]
+    async fn connects_with_ca_bundle() {
+        let pki = TestPki::new();
+        let port = start_echo_server(&pki, false).await;
+
+        let config = TlsConfig {
+            ca_location: Some(pki.ca_location()),
+            ..Default::default()
+        };
+        let settings = TlsSettings::from_config(&config, &format!("localhost:{}", port)).unwrap();
+
+        assert_eq!(echo_frame(&settings, port).await.unwrap(), b"hello sudeep".to_vec());
+    }
+
+    #[tokio::test]
+    async fn connects_with_client_certificate() {
+        let pki = TestPki::new();
+        let port = start_echo_server(&pki, true).await;
+        let (certificate_location, key_location) = pki.issue("producer");
+
+        let config = TlsConfig {
+            ca_location: Some(pki.ca_location()),
+            certificate_location: Some(certificate_location),
+            key_location: Some(key_location),
+            ..Default::default()
+        };
+        let settings = TlsSettings::from_config(&config, &format!("localhost:{}", port)).unwrap();
+
+        assert_eq!(echo_frame(&settings, port).await.unwrap(), b"hello sudeep".to_vec());
+    }
+
+    #[tokio::test]
+    async fn server_name_override_is_used_for_verification() {
+        let pki = TestPki::new();
+        let port = start_echo_server(&pki, false).await;
+
+        // connecting by ip would fail verification without the override
+        let config = TlsConfig {
+            ca_location: Some(pki.ca_location()),
+            server_name: Some("localhost".to_string()),
+            ..Default::default()
+        };
+        let settings = TlsSettings::from_config(&config, &format!("127.0.0.1:{}", port)).unwrap();
+
+        assert_eq!(echo_frame(&settings, port).await.unwrap(), b"hello sudeep".to_vec());
+    }
+
+    #[tokio::test]
+    async fn rejects_untrusted_certificate() {
+        let pki = TestPki::new();
+        let port = start_echo_server(&pki, false).await;
+
+        let settings = TlsSettings::from_config(&TlsConfig::default(), &format!("localhost:{}", port)).unwrap();
+
+        assert!(echo_frame(&settings, port).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn insecure_mode_skips_verification() {
+        let pki = TestPki::new();
+        let port = start_echo_server(&pki, false).await;
+
+        let config = TlsConfig {
+            insecure_skip_verify: true,
+            ..Default::default()
+        };
+        let settings = TlsSettings::from_config(&config, &format!("127.0.0.1:{}", port)).unwrap();
+
+        assert_eq!(echo_frame(&settings, port).await.unwrap(), b"hello sudeep".to_vec());
+    }
+
+    #[test]
+    fn rejects_certificate_without_key() {
+        let config = TlsConfig {
+            certificate_location: Some("client.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(TlsSettings::from_config(&config, "localhost:9092").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use std::marker::PhantomData;
+use std::time::Duration;
+use serde::Serialize;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use crate::brahmaputra::byte_buffers::concrete_functions::serializers::{JsonSerializer, SerializationError, Serializer, StringSerializer};
+
+// a producer that serializes keys and values itself, raw string keys and json values unless other serializers are given
+// keys are written as they are so a message lands on the same partition as one pushed with the same key through Producer
+#[derive(Debug)]
+pub struct TypedProducer<K, V, KS = StringSerializer, VS = JsonSerializer> {
+    producer: Producer,
+    key_serializer: KS,
+    value_serializer: VS,
+    types: PhantomData<fn(&K, &V)>,
+}
+
+impl<K: AsRef<str>, V: Serialize> TypedProducer<K, V> {
+    pub fn new(producer: Producer) -> TypedProducer<K, V> {
+        TypedProducer::with_serializers(producer, StringSerializer, JsonSerializer)
+    }
+}
+
+impl<K, V, KS: Serializer<K>, VS: Serializer<V>> TypedProducer<K, V, KS, VS> {
+    pub fn with_serializers(producer: Producer, key_serializer: KS, value_serializer: VS) -> TypedProducer<K, V, KS, VS> {
+        TypedProducer {
+            producer,
+            key_serializer,
+            value_serializer,
+            types: PhantomData,
+        }
+    }
+
+    pub async fn connect_producer(&mut self) -> Result<(), ProducerError> {
+        self.producer.connect_producer().await
+    }
+
+    pub async fn push(&mut self, topic: String, key: &K, value: &V) -> Result<(), ProducerError> {
+        let (key, msg) = self.serialize(&topic, key, value)?;
+        self.producer.push(topic, key, msg).await
+    }
+
+    pub fn try_push(&mut self, topic: String, key: &K, value: &V) -> Result<(), ProducerError> {
+        let (key, msg) = self.serialize(&topic, key, value)?;
+        self.producer.try_push(topic, key, msg)
+    }
+
+    pub async fn push_and_wait(&mut self, topic: String, key: &K, value: &V) -> Result<(), ProducerError> {
+        let (key, msg) = self.serialize(&topic, key, value)?;
+        self.producer.push_and_wait(topic, key, msg).await
+    }
+
+    pub async fn flush(&mut self, timeout: Duration) -> Result<(), ProducerError> {
+        self.producer.flush(timeout).await
+    }
+
+    pub async fn close(&mut self, timeout: Duration) -> Result<(), ProducerError> {
+        self.producer.close(timeout).await
+    }
+
+    // transactions and everything else that does not take a key or value go through the inner producer
+    pub fn producer(&mut self) -> &mut Producer {
+        &mut self.producer
+    }
+
+    pub fn into_inner(self) -> Producer {
+        self.producer
+    }
+
+    // nothing is queued unless both the key and the value serialize
+    fn serialize(&self, topic: &str, key: &K, value: &V) -> Result<(String, Vec<u8>), ProducerError> {
+        let key = self.key_serializer.serialize(topic, key)?;
+        let key = String::from_utf8(key).map_err(SerializationError::InvalidKey)?;
+        let msg = self.value_serializer.serialize(topic, value)?;
+        Ok((key, msg))
+    }
+}
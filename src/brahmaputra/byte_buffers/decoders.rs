@@ -1,2 +1,2 @@
-mod producers;
+pub mod producers;
 mod consumers;
\ No newline at end of file
@@ -0,0 +1,204 @@
+use crate::brahmaputra::byte_buffers::concrete_functions::api_versions::ApiVersionRange;
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::ProducerIdentity;
+
+pub fn producer_decode_init_id(total_buf: Vec<u8>) -> Result<ProducerIdentity, ProducerError> {
+
+    let mut bb = ByteBuff{
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    bb.wrap(total_buf);
+
+    // putting as P
+    let _client_type = bb.get_string();
+
+    // putting Error Code
+    let error_code = bb.get_int();
+
+    // putting error message
+    let error_msg = bb.get_string();
+
+    // producer id assigned by the broker
+    let producer_id = bb.get_long();
+
+    // epoch of the producer id, bumped every time the id is handed out again
+    let producer_epoch = bb.get_short();
+
+    if let Some(err) = BrokerError::from_code(error_code) {
+        return Err(ProducerError::Broker(err, format!("broker refused producer id: {}", error_msg)));
+    }
+
+    Ok(ProducerIdentity {
+        producer_id,
+        producer_epoch,
+    })
+}
+
+pub struct SaslAuthenticateResponse {
+    pub error_code: i32,
+    pub error_msg: String,
+    pub auth_bytes: Vec<u8>,
+    pub session_lifetime_ms: i64,
+}
+
+pub fn producer_decode_sasl_handshake(total_buf: Vec<u8>) -> (i32, String) {
+
+    let mut bb = ByteBuff{
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    bb.wrap(total_buf);
+
+    // putting as P
+    let _client_type = bb.get_string();
+
+    // putting Error Code
+    let error_code = bb.get_int();
+
+    // putting error message
+    let error_msg = bb.get_string();
+
+    (error_code, error_msg)
+}
+
+pub fn producer_decode_sasl_authenticate(total_buf: Vec<u8>) -> SaslAuthenticateResponse {
+
+    let mut bb = ByteBuff{
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    bb.wrap(total_buf);
+
+    // putting as P
+    let _client_type = bb.get_string();
+
+    // putting Error Code
+    let error_code = bb.get_int();
+
+    // putting error message
+    let error_msg = bb.get_string();
+
+    // challenge for the next step of the mechanism
+    let auth_bytes = bb.get();
+
+    // how long the broker keeps the session authenticated, 0 when it never expires
+    let session_lifetime_ms = bb.get_long();
+
+    SaslAuthenticateResponse {
+        error_code,
+        error_msg,
+        auth_bytes,
+        session_lifetime_ms,
+    }
+}
+
+pub struct ApiVersionsResponse {
+    pub error_code: i32,
+    pub error_msg: String,
+    pub api_versions: Vec<(i32, ApiVersionRange)>,
+}
+
+pub fn producer_decode_api_versions(total_buf: Vec<u8>) -> ApiVersionsResponse {
+
+    let mut bb = ByteBuff{
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    bb.wrap(total_buf);
+
+    // putting as P
+    let _client_type = bb.get_string();
+
+    // putting Error Code
+    let error_code = bb.get_int();
+
+    // putting error message
+    let error_msg = bb.get_string();
+
+    let mut api_versions = Vec::new();
+
+    // the broker only lists message codes when it accepted the request
+    if error_code == 0 {
+        // number of message codes the broker supports
+        let count = bb.get_int();
+
+        for _ in 0..count {
+            let message_code = bb.get_int();
+            let min_version = bb.get_short();
+            let max_version = bb.get_short();
+
+            api_versions.push((message_code, ApiVersionRange {
+                min_version,
+                max_version,
+            }));
+        }
+    }
+
+    ApiVersionsResponse {
+        error_code,
+        error_msg,
+        api_versions,
+    }
+}
+
+// reads the partition back out of an encoded message frame, every version puts it at the same place
+// the partition and key a produce frame was encoded for
+pub fn producer_decode_frame_route(frame: Vec<u8>) -> (u32, String) {
+
+    let mut bb = ByteBuff{
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    bb.wrap(frame);
+
+    // total length of the frame
+    let _length = bb.get_long();
+
+    // version number
+    let version = bb.get_string();
+
+    // topic
+    let _topic = bb.get_string();
+
+    // message type either producer or consumer
+    let _client_type = bb.get_string();
+
+    // message code for producer
+    let _message_code = bb.get_int();
+
+    // compression
+    let _compression = bb.get_string();
+
+    // acks
+    let _acks = bb.get_string();
+
+    // partition
+    let partition = bb.get_int() as u32;
+
+    // producer id, epoch, sequence and transactional flag from v2 on
+    if version != "V_1" {
+        let _producer_id = bb.get_long();
+        let _producer_epoch = bb.get_short();
+        let _sequence = bb.get_int();
+        let _transactional = bb.get_bool();
+    }
+
+    // unique key
+    let _unique_key = bb.get_string();
+
+    // key
+    (partition, bb.get_string())
+}
@@ -1,2 +1,11 @@
 mod producers;
-mod consumers;
\ No newline at end of file
+mod consumers;
+mod transactions;
+mod connections;
+mod authentication;
+mod api_versions;
+mod backpressure;
+mod spill;
+mod rate_limits;
+mod handles;
+mod writers;
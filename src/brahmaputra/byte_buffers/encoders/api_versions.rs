@@ -0,0 +1,91 @@
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::warn;
+use uuid::Uuid;
+use crate::brahmaputra::byte_buffers::concrete_functions::api_versions::{ApiVersionRange, CLIENT_API_VERSIONS, LEGACY_BROKER_API_VERSIONS};
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::MessageCode;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::broker_api_versions;
+use crate::brahmaputra::byte_buffers::decoders::producers::producer_decode_api_versions;
+use crate::brahmaputra::byte_buffers::encoders::connections::{read_frame, write_frame};
+
+// asks the broker which frame versions it supports, runs first on every new connection
+pub(super) async fn negotiate_api_versions(stream: &mut ProducerStream, response_timeout: Duration) -> Result<(), ProducerError> {
+    write_frame(stream, producer_encode_api_versions().as_slice()).await?;
+
+    let api_versions: Vec<(i32, ApiVersionRange)> = match timeout(response_timeout, read_frame(stream)).await {
+        Ok(frame) => {
+            let response = producer_decode_api_versions(frame?);
+            match BrokerError::from_code(response.error_code) {
+                None => response.api_versions,
+                // brokers that predate the handshake reject the message code
+                Some(BrokerError::UnsupportedVersion) | Some(BrokerError::InvalidRequest) => legacy_api_versions(),
+                Some(err) => {
+                    return Err(ProducerError::Broker(err, format!("api versions handshake failed: {}", response.error_msg)));
+                }
+            }
+        }
+        // or ignore it without an answer
+        Err(_) => {
+            warn!(timeout_ms = response_timeout.as_millis() as u64, "broker did not answer the api versions handshake, assuming it predates the handshake");
+            legacy_api_versions()
+        }
+    };
+
+    // every connection goes to the same cluster, the latest answer wins
+    broker_api_versions.retain(|code, _| api_versions.iter().any(|(broker_code, _)| broker_code == code));
+    for (code, range) in api_versions {
+        broker_api_versions.insert(code, range);
+    }
+
+    Ok(())
+}
+
+fn legacy_api_versions() -> Vec<(i32, ApiVersionRange)> {
+    LEGACY_BROKER_API_VERSIONS.iter().map(|(code, range)| (*code as i32, *range)).collect()
+}
+
+fn producer_encode_api_versions() -> Vec<u8> {
+    let mut bb = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    // into big endian format
+    bb.init("big".to_string());
+
+    // version number
+    bb.put_string("V_1".to_string());
+
+    // message type either producer or consumer
+    bb.put_string("P".to_string());
+
+    // message code for the api versions handshake
+    bb.put_int(MessageCode::ApiVersions as i32);
+
+    // put unique key
+    bb.put_string(Uuid::new_v4().to_string());
+
+    // versions the client can encode so the broker can log outdated clients
+    bb.put_int(CLIENT_API_VERSIONS.len() as i32);
+    for (code, range) in CLIENT_API_VERSIONS.iter() {
+        bb.put_int(*code as i32);
+        bb.put_short(range.min_version);
+        bb.put_short(range.max_version);
+    }
+
+    // wrapping the message into another byte array to get its total length
+    let mut wrap_byte = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    wrap_byte.put(bb.to_array());
+
+    wrap_byte.to_array()
+}
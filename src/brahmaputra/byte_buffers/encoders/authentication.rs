@@ -0,0 +1,132 @@
+use std::io::{Error, ErrorKind};
+use std::time::Duration;
+use tokio::time::timeout;
+use uuid::Uuid;
+use crate::brahmaputra::byte_buffers::concrete_functions::authentication::{AuthError, Authenticator};
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::MessageCode;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+use crate::brahmaputra::byte_buffers::decoders::producers::{producer_decode_sasl_authenticate, producer_decode_sasl_handshake};
+use crate::brahmaputra::byte_buffers::encoders::connections::{read_frame, write_frame};
+
+// runs the SASL exchange on a fresh connection, returns how long the broker keeps the session authenticated
+// a broker that stops answering fails the connection once the whole exchange took longer than exchange_timeout
+pub(super) async fn authenticate(stream: &mut ProducerStream, authenticator: &dyn Authenticator, exchange_timeout: Duration) -> Result<Option<Duration>, AuthError> {
+    match timeout(exchange_timeout, exchange(stream, authenticator)).await {
+        Ok(session_lifetime) => session_lifetime,
+        Err(_) => Err(AuthError::Io(Error::new(ErrorKind::TimedOut, format!("SASL exchange took longer than {} ms", exchange_timeout.as_millis())))),
+    }
+}
+
+async fn exchange(stream: &mut ProducerStream, authenticator: &dyn Authenticator) -> Result<Option<Duration>, AuthError> {
+    let mechanism = authenticator.mechanism();
+
+    write_frame(stream, producer_encode_sasl_handshake(mechanism.to_string()).as_slice()).await?;
+    let (error_code, error_msg) = producer_decode_sasl_handshake(read_frame(stream).await?);
+
+    match BrokerError::from_code(error_code) {
+        None => {}
+        Some(BrokerError::UnsupportedSaslMechanism) => {
+            return Err(AuthError::UnsupportedMechanism(format!("{}: {}", mechanism, error_msg)));
+        }
+        Some(err) => {
+            return Err(AuthError::AuthenticationFailed(format!("{}: {}", err, error_msg)));
+        }
+    }
+
+    let mut exchange = authenticator.start()?;
+    let mut challenge = Vec::new();
+    let mut session_lifetime = None;
+
+    while let Some(auth_bytes) = exchange.step(challenge.as_slice())? {
+        write_frame(stream, producer_encode_sasl_authenticate(auth_bytes).as_slice()).await?;
+        let response = producer_decode_sasl_authenticate(read_frame(stream).await?);
+
+        if let Some(err) = BrokerError::from_code(response.error_code) {
+            return Err(AuthError::AuthenticationFailed(format!("{}: {}", err, response.error_msg)));
+        }
+
+        if response.session_lifetime_ms > 0 {
+            session_lifetime = Some(Duration::from_millis(response.session_lifetime_ms as u64));
+        }
+
+        challenge = response.auth_bytes;
+    }
+
+    Ok(session_lifetime)
+}
+
+fn producer_encode_sasl_handshake(mechanism: String) -> Vec<u8> {
+    let mut bb = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    // into big endian format
+    bb.init("big".to_string());
+
+    // version number
+    bb.put_string("V_1".to_string());
+
+    // message type either producer or consumer
+    bb.put_string("P".to_string());
+
+    // message code for the SASL handshake
+    bb.put_int(MessageCode::SaslHandshake as i32);
+
+    // put unique key
+    bb.put_string(Uuid::new_v4().to_string());
+
+    // mechanism the client wants to use
+    bb.put_string(mechanism);
+
+    // wrapping the message into another byte array to get its total length
+    let mut wrap_byte = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    wrap_byte.put(bb.to_array());
+
+    wrap_byte.to_array()
+}
+
+fn producer_encode_sasl_authenticate(auth_bytes: Vec<u8>) -> Vec<u8> {
+    let mut bb = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    // into big endian format
+    bb.init("big".to_string());
+
+    // version number
+    bb.put_string("V_1".to_string());
+
+    // message type either producer or consumer
+    bb.put_string("P".to_string());
+
+    // message code for a SASL authentication step
+    bb.put_int(MessageCode::SaslAuthenticate as i32);
+
+    // put unique key
+    bb.put_string(Uuid::new_v4().to_string());
+
+    // mechanism specific payload
+    bb.put(auth_bytes);
+
+    // wrapping the message into another byte array to get its total length
+    let mut wrap_byte = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    wrap_byte.put(bb.to_array());
+
+    wrap_byte.to_array()
+}
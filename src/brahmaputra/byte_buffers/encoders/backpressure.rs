@@ -0,0 +1,314 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::time::{timeout_at, Instant};
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{buffer_budget, ChannelReader, ChannelWriter, DeliveryOutcome, DeliveryReport, dropped_messages, delivery_waiters, FrameKind, in_flight_messages, Producer, queued_frames, QueuedMessage, rate_limits, unacked_messages, writer_queues};
+use crate::brahmaputra::byte_buffers::concrete_functions::rate_limiter::ProducerRateLimits;
+use crate::brahmaputra::byte_buffers::encoders::producers::{frame_written, message_finished, message_settled};
+use crate::brahmaputra::byte_buffers::encoders::rate_limits::{release_rate_limits, try_rate_limits, wait_for_rate_limits};
+use crate::brahmaputra::byte_buffers::encoders::spill::wait_spill_synced;
+
+impl Producer {
+    // waits for buffer memory and room in the queue under the Block policy, every other policy never waits
+    pub(super) async fn enqueue_message(&self, message: QueuedMessage) -> Result<(), ProducerError> {
+        if self.backpressure_policy.unwrap_or_default() != BackpressurePolicy::Block {
+            return self.try_enqueue_message(message);
+        }
+
+        self.message_queued();
+
+        let sender = ChannelWriter.read().await.as_ref().cloned();
+        let budget = buffer_budget.read().await.as_ref().cloned();
+        let (sender, budget) = match (sender, budget) {
+            (Some(sender), Some(budget)) => (sender, budget),
+            _ => {
+                return Err(self.message_unqueued(&message, ProducerError::NotConnected));
+            }
+        };
+        let limits = rate_limits.read().await.as_ref().cloned();
+
+        self.enqueue_to(message, &sender, &budget, limits.as_deref()).await
+    }
+
+    // message_queued has counted the message already, a producer handle brings its own sender, budget and limits
+    pub(super) async fn enqueue_to(&self, message: QueuedMessage, sender: &Sender<QueuedMessage>, budget: &Arc<Semaphore>, limits: Option<&ProducerRateLimits>) -> Result<(), ProducerError> {
+        let bytes = match self.reserved_bytes(&message) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return Err(self.message_unqueued(&message, err));
+            }
+        };
+
+        // with a spill journal, messages that cannot be held in memory or written now go to disk
+        let message = match self.spill(message, budget, sender) {
+            Some(message) => message,
+            None => {
+                wait_spill_synced().await;
+                return Ok(());
+            }
+        };
+
+        // rate limits slow the caller down before the frame enters the queue, apart from the buffer timeout
+        wait_for_rate_limits(limits, &message.topic, message.frame.len()).await;
+
+        let topic = message.topic.to_string();
+        let frame_len = message.frame.len();
+        let queued = self.enqueue_reserved(message, sender, budget, bytes).await;
+        if queued.is_err() {
+            release_rate_limits(limits, &topic, frame_len);
+        }
+        queued
+    }
+
+    // waits for buffer memory and room in the queue for a message that got through the rate limits
+    async fn enqueue_reserved(&self, mut message: QueuedMessage, sender: &Sender<QueuedMessage>, budget: &Arc<Semaphore>, bytes: u32) -> Result<(), ProducerError> {
+        // one deadline covers waiting for buffer memory and for a slot in the queue
+        let deadline = Instant::now() + Duration::from_millis(self.buffer_full_timeout_ms.unwrap_or(60000));
+
+        let permit = match timeout_at(deadline, budget.clone().acquire_many_owned(bytes)).await {
+            Ok(Ok(permit)) => permit,
+            Ok(Err(_)) => {
+                return Err(self.message_unqueued(&message, ProducerError::NotConnected));
+            }
+            Err(_) => {
+                return Err(self.message_unqueued(&message, ProducerError::Timeout("waiting for producer buffer memory".to_string())));
+            }
+        };
+        hold_permit(&mut message, permit);
+
+        // reserving first keeps the message here when the wait for room fails, so its outcome can still be reported
+        match timeout_at(deadline, sender.reserve()).await {
+            Ok(Ok(slot)) => {
+                slot.send(message);
+                Ok(())
+            }
+            Ok(Err(_)) => Err(self.message_unqueued(&message, ProducerError::NotConnected)),
+            Err(_) => Err(self.message_unqueued(&message, ProducerError::Timeout("waiting for room in the producer queue".to_string()))),
+        }
+    }
+
+    pub(super) fn try_enqueue_message(&self, message: QueuedMessage) -> Result<(), ProducerError> {
+        self.message_queued();
+
+        // the locks are only held for writing while the producer connects or closes
+        let sender = ChannelWriter.try_read().ok().and_then(|sender| sender.as_ref().cloned());
+        let budget = buffer_budget.try_read().ok().and_then(|budget| budget.as_ref().cloned());
+        let (sender, budget) = match (sender, budget) {
+            (Some(sender), Some(budget)) => (sender, budget),
+            _ => {
+                return Err(self.message_unqueued(&message, ProducerError::NotConnected));
+            }
+        };
+        let limits = rate_limits.try_read().ok().and_then(|limits| limits.as_ref().cloned());
+
+        self.try_enqueue_to(message, &sender, &budget, limits.as_deref())
+    }
+
+    // the never waiting counterpart of enqueue_to
+    pub(super) fn try_enqueue_to(&self, message: QueuedMessage, sender: &Sender<QueuedMessage>, budget: &Arc<Semaphore>, limits: Option<&ProducerRateLimits>) -> Result<(), ProducerError> {
+        let bytes = match self.reserved_bytes(&message) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                return Err(self.message_unqueued(&message, err));
+            }
+        };
+
+        // with a spill journal, messages that cannot be held in memory or written now go to disk
+        // this path never waits, so the frame can still be on its way to disk when the push returns
+        let message = match self.spill(message, budget, sender) {
+            Some(message) => message,
+            None => {
+                return Ok(());
+            }
+        };
+
+        let policy = self.backpressure_policy.unwrap_or_default();
+
+        if !try_rate_limits(limits, &message.topic, message.frame.len()) {
+            // dropping older messages would not free up any rate, so the new one goes
+            if policy == BackpressurePolicy::DropOldest || policy == BackpressurePolicy::DropNewest {
+                self.message_dropped(message.report(DeliveryOutcome::Dropped, "rate limit reached".to_string()));
+                return Ok(());
+            }
+            return Err(self.message_unqueued(&message, ProducerError::RateLimited));
+        }
+
+        // the rate is only used up by a message that makes it into the queue
+        let topic = message.topic.to_string();
+        let frame_len = message.frame.len();
+        let queued = self.try_enqueue_reserved(message, sender, budget, bytes, policy);
+        if !matches!(queued, Ok(true)) {
+            release_rate_limits(limits, &topic, frame_len);
+        }
+        queued.map(|_| ())
+    }
+
+    // true when the message was queued, false when the backpressure policy dropped a message instead of it
+    fn try_enqueue_reserved(&self, mut message: QueuedMessage, sender: &Sender<QueuedMessage>, budget: &Arc<Semaphore>, bytes: u32, policy: BackpressurePolicy) -> Result<bool, ProducerError> {
+        let permit = loop {
+            match budget.clone().try_acquire_many_owned(bytes) {
+                Ok(permit) => break permit,
+                Err(_) if policy == BackpressurePolicy::DropOldest && (self.drop_oldest_waiting() || self.drop_oldest_queued()) => {}
+                Err(_) if policy == BackpressurePolicy::DropOldest || policy == BackpressurePolicy::DropNewest => {
+                    // nothing queued is left to free, memory is held by frames waiting for acks
+                    self.message_dropped(message.report(DeliveryOutcome::Dropped, "buffer memory is full".to_string()));
+                    return Ok(false);
+                }
+                Err(_) => {
+                    return Err(self.message_unqueued(&message, ProducerError::QueueFull));
+                }
+            }
+        };
+        hold_permit(&mut message, permit);
+
+        let message = match sender.try_send(message) {
+            Ok(_) => {
+                return Ok(true);
+            }
+            Err(TrySendError::Closed(message)) => {
+                return Err(self.message_unqueued(&message, ProducerError::NotConnected));
+            }
+            Err(TrySendError::Full(message)) => message,
+        };
+
+        match policy {
+            BackpressurePolicy::DropNewest => {
+                self.message_dropped(message.report(DeliveryOutcome::Dropped, "producer queue is full".to_string()));
+                Ok(false)
+            }
+            // the room is needed in the producer queue, so the oldest message there goes
+            BackpressurePolicy::DropOldest => {
+                self.drop_oldest_queued();
+
+                match sender.try_send(message) {
+                    Ok(_) => Ok(true),
+                    // still no room, so the new message is the one that goes
+                    Err(TrySendError::Full(message)) => {
+                        self.message_dropped(message.report(DeliveryOutcome::Dropped, "producer queue is full".to_string()));
+                        Ok(false)
+                    }
+                    Err(TrySendError::Closed(message)) => Err(self.message_unqueued(&message, ProducerError::NotConnected)),
+                }
+            }
+            // try_push never waits, so blocking fails just like fail fast
+            BackpressurePolicy::FailFast | BackpressurePolicy::Block => Err(self.message_unqueued(&message, ProducerError::QueueFull)),
+        }
+    }
+
+    // buffer memory shared by queued and in flight frames
+    pub(super) fn buffer_memory_limit(&self) -> usize {
+        self.buffer_memory_bytes.unwrap_or(33554432) as usize
+    }
+
+    // a frame larger than the whole budget would wait forever
+    fn reserved_bytes(&self, message: &QueuedMessage) -> Result<u32, ProducerError> {
+        let bytes = message.frame.len();
+        if bytes > self.buffer_memory_limit() {
+            return Err(ProducerError::Encode(format!(
+                "message of {} bytes is larger than the producer buffer memory of {} bytes",
+                bytes, self.buffer_memory_limit()
+            )));
+        }
+
+        u32::try_from(bytes).map_err(|_| ProducerError::Encode(format!("message of {} bytes is too large", bytes)))
+    }
+
+    // the oldest messages wait in the writer queues, they hold buffer memory until they are written
+    fn drop_oldest_waiting(&self) -> bool {
+        let oldest = writer_queues.iter()
+            .filter_map(|queue| {
+                let frames = queue.frames.lock().unwrap();
+                let queued_at = frames.iter().find(|outgoing| outgoing.kind == FrameKind::Message)?.queued_at;
+                Some((queued_at, Arc::clone(queue.value())))
+            })
+            .min_by_key(|(queued_at, _)| *queued_at);
+
+        let queue = match oldest {
+            Some((_, queue)) => queue,
+            None => {
+                return false;
+            }
+        };
+
+        // the writer may have taken it in the meantime, then the next message in that queue goes
+        let dropped = {
+            let mut frames = queue.frames.lock().unwrap();
+            frames.iter()
+                .position(|outgoing| outgoing.kind == FrameKind::Message)
+                .and_then(|position| frames.remove(position))
+        };
+
+        match dropped {
+            Some(dropped) => {
+                queue.room.notify_one();
+                self.message_dropped(dropped.report(DeliveryOutcome::Dropped, "dropped for a newer message".to_string()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the dispatcher only holds the receiver while it waits on an empty queue
+    fn drop_oldest_queued(&self) -> bool {
+        let oldest = match ChannelReader.try_lock() {
+            Ok(mut receiver) => receiver.as_mut().and_then(|receiver| receiver.try_recv().ok()),
+            Err(_) => None,
+        };
+
+        match oldest {
+            Some(oldest) => {
+                self.message_dropped(oldest.report(DeliveryOutcome::Dropped, "dropped for a newer message".to_string()));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // messages sent with acks 0 never get an ack, so flush only waits for them to be written
+    pub(super) fn message_queued(&self) {
+        queued_frames.fetch_add(1, Ordering::SeqCst);
+        if self.acks() != "0" {
+            unacked_messages.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    // undoes message_queued for a message that never reached the dispatcher, the error is handed back for the caller
+    fn message_unqueued(&self, message: &QueuedMessage, err: ProducerError) -> ProducerError {
+        // the caller gets the error directly, nobody is left to wait
+        delivery_waiters.remove(&message.unique_key);
+        self.settle_unsent(message.report(DeliveryOutcome::Failed, err.to_string()));
+        err
+    }
+
+    fn message_dropped(&self, report: DeliveryReport) {
+        self.settle_unsent(report);
+        dropped_messages.fetch_add(1, Ordering::SeqCst);
+    }
+
+    // the final outcome of a message the writer never got to
+    fn settle_unsent(&self, report: DeliveryReport) {
+        in_flight_messages.remove(&report.unique_key);
+        message_finished(&report, &self.interceptors);
+        frame_written();
+        if self.acks() != "0" {
+            message_settled();
+        }
+    }
+}
+
+// tracked frames keep their memory until they are acked, the rest until they are written
+fn hold_permit(message: &mut QueuedMessage, permit: OwnedSemaphorePermit) {
+    match in_flight_messages.get_mut(&message.unique_key) {
+        Some(mut in_flight) => {
+            in_flight.permit = Some(permit);
+        }
+        None => {
+            message.permit = Some(permit);
+        }
+    }
+}
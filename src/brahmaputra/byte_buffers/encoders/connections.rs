@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{Error, ErrorKind, IoSlice};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use bytes::Bytes;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{oneshot, RwLock, Semaphore};
+use tokio::time::{interval, sleep, MissedTickBehavior};
+use tracing::{debug, info, info_span, warn, Instrument};
+use uuid::Uuid;
+use crate::brahmaputra::byte_buffers::concrete_functions::api_versions::{is_supported, negotiated_version};
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::MessageCode;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{connection_last_seen, connection_session_expiry, connection_throttled_until, connection_windows, ConnectionSettings, EncodedFrame, first_written_at, in_flight_slots, pending_requests, pool_socket_reader, pool_socket_writer, producer_closed, producer_metrics, producer_tasks, reconnecting_connections, socket_current_conn, socket_reader_tasks, SocketWriter, writer_queues};
+use crate::brahmaputra::byte_buffers::encoders::api_versions::negotiate_api_versions;
+use crate::brahmaputra::byte_buffers::encoders::authentication::authenticate;
+use crate::brahmaputra::byte_buffers::encoders::producers::{producer_decode_msg, resend_unacked};
+use crate::brahmaputra::byte_buffers::encoders::writers::queue_control;
+
+// opens a connection, negotiates frame versions and authenticates it, returns how long the authenticated session lasts
+pub(super) async fn open_connection(settings: &ConnectionSettings) -> Result<(ProducerStream, Option<Duration>), ProducerError> {
+    let stream = TcpStream::connect(settings.servers.to_string()).await?;
+
+    let mut stream = match &settings.tls {
+        Some(tls) => tls.connect(stream).await?,
+        None => ProducerStream::Plain(stream),
+    };
+
+    // learning the broker's frame versions before anything else is sent, a silent broker gets the heartbeat timeout
+    negotiate_api_versions(&mut stream, Duration::from_millis(settings.heartbeat_timeout_ms)).await?;
+
+    let session_lifetime = match &settings.authenticator {
+        Some(authenticator) => {
+            negotiated_version(MessageCode::SaslHandshake)?;
+            authenticate(&mut stream, authenticator.as_ref(), Duration::from_millis(settings.heartbeat_timeout_ms)).await?
+        }
+        None => None,
+    };
+
+    Ok((stream, session_lifetime))
+}
+
+pub(super) fn add_to_pool(conn_number: i32, conn: ProducerStream, session_lifetime: Option<Duration>, settings: &ConnectionSettings) {
+    let (read_half, write_half) = tokio::io::split(conn);
+
+    pool_socket_writer.insert(conn_number, Arc::new(RwLock::new(Some(write_half))));
+
+    pool_socket_reader.insert(conn_number, Arc::new(RwLock::new(Some(read_half))));
+
+    connection_last_seen.insert(conn_number, Instant::now());
+
+    // a fresh connection starts with an empty window, the frames its writer kept can go out now
+    connection_windows.insert(conn_number, Arc::new(Semaphore::new(settings.max_in_flight_per_connection)));
+    wake_writer(conn_number);
+
+    // reconnecting at 90% of the session lifetime picks up a fresh token before the broker drops the session
+    if let Some(session_lifetime) = session_lifetime {
+        connection_session_expiry.insert(conn_number, Instant::now() + session_lifetime.mul_f64(0.9));
+    }
+}
+
+pub(super) async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, frame: &[u8]) -> Result<(), Error> {
+    writer.write_all(frame).await?;
+    writer.flush().await
+}
+
+// writes the parts with vectored writes, a batch of frames goes out in one syscall when the socket takes it all
+// flushing is left to the caller, on an error it gets the bytes written so far to tell which frames made it
+pub(super) async fn write_vectored_all<W: AsyncWrite + Unpin>(writer: &mut W, parts: &[&[u8]]) -> Result<(), (usize, Error)> {
+    let mut slices: Vec<IoSlice> = parts.iter().filter(|part| !part.is_empty()).map(|part| IoSlice::new(part)).collect();
+    let mut remaining = slices.as_mut_slice();
+    let mut written = 0;
+
+    while !remaining.is_empty() {
+        let n = match writer.write_vectored(remaining).await {
+            Ok(0) => {
+                return Err((written, Error::new(ErrorKind::WriteZero, "connection accepted no more bytes")));
+            }
+            Ok(n) => n,
+            Err(err) => {
+                return Err((written, err));
+            }
+        };
+        written += n;
+        IoSlice::advance_slices(&mut remaining, n);
+    }
+
+    Ok(())
+}
+
+// an ack freed a slot or the connection came back, its writer looks at the frames it kept again
+pub(super) fn wake_writer(conn_number: i32) {
+    if let Some(queue) = writer_queues.get(&conn_number) {
+        queue.waker.notify_one();
+    }
+}
+
+pub(super) async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut length_buf = [0u8; 8];
+    reader.read_exact(&mut length_buf).await?;
+
+    let mut total_buf = vec![0u8; usize::from_be_bytes(length_buf)];
+    reader.read_exact(&mut total_buf).await?;
+
+    Ok(total_buf)
+}
+
+// picks the next live connection round robin, waiting for reconnection when every connection is down
+// connections the broker throttled are skipped, unless every live connection is throttled
+pub(super) async fn next_connection(settings: &ConnectionSettings) -> (i32, SocketWriter) {
+    loop {
+        let current_conn = socket_current_conn.read().await.unwrap_or(0);
+        let mut throttled: Option<(i32, SocketWriter, Instant)> = None;
+
+        for offset in 1..=settings.pool_size {
+            let conn_number = (current_conn + offset) % settings.pool_size;
+
+            let socket = match pool_socket_writer.get(&conn_number) {
+                Some(socket) => socket.value().clone(),
+                None => {
+                    continue;
+                }
+            };
+
+            match throttled_until(conn_number) {
+                Some(until) => {
+                    if throttled.as_ref().map(|(_, _, earliest)| until < *earliest).unwrap_or(true) {
+                        throttled = Some((conn_number, socket, until));
+                    }
+                }
+                None => {
+                    let _ = socket_current_conn.write().await.insert(conn_number);
+                    return (conn_number, socket);
+                }
+            }
+        }
+
+        // waiting out the throttle that ends first
+        if let Some((conn_number, socket, until)) = throttled {
+            sleep(until.saturating_duration_since(Instant::now())).await;
+            let _ = socket_current_conn.write().await.insert(conn_number);
+            return (conn_number, socket);
+        }
+
+        sleep(Duration::from_millis(settings.reconnect_backoff_ms)).await;
+    }
+}
+
+// every frame of a partition goes out on the same connection, so the broker sees them in the order they were queued
+// the partitions of a topic are spread over the pool instead of all hashing to wherever the topic lands
+pub(super) fn pinned_connection(topic: &str, partition: u32, pool_size: i32) -> i32 {
+    let mut hasher = DefaultHasher::new();
+    topic.hash(&mut hasher);
+
+    (hasher.finish().wrapping_add(partition as u64) % pool_size.max(1) as u64) as i32
+}
+
+// the broker asked for a pause on this connection, frames pinned to it wait and control frames go to the other connections
+pub(super) fn throttle_connection(conn_number: i32, throttle_time: Duration) {
+    let until = Instant::now() + throttle_time;
+
+    // an ack asking for a shorter pause does not cut a longer one short
+    connection_throttled_until.entry(conn_number)
+        .and_modify(|current| *current = (*current).max(until))
+        .or_insert(until);
+
+    producer_metrics.connection(conn_number).throttle_time_ms.fetch_add(throttle_time.as_millis() as u64, Ordering::Relaxed);
+    debug!(connection = conn_number, throttle_ms = throttle_time.as_millis() as u64, "broker throttled connection");
+}
+
+pub(super) fn throttled_until(conn_number: i32) -> Option<Instant> {
+    let until = connection_throttled_until.get(&conn_number).map(|until| *until.value())?;
+    if until > Instant::now() {
+        return Some(until);
+    }
+
+    connection_throttled_until.remove_if(&conn_number, |_, current| *current <= Instant::now());
+    None
+}
+
+pub(super) fn spawn_socket_reader(conn_number: i32, settings: Arc<ConnectionSettings>) {
+    let socket = match pool_socket_reader.get(&conn_number) {
+        Some(socket) => socket.value().clone(),
+        None => {
+            return;
+        }
+    };
+
+    let task = tokio::spawn(async move {
+        let mut guard = socket.write().await;
+        let sock = match guard.as_mut() {
+            Some(sock) => sock,
+            None => {
+                return;
+            }
+        };
+
+        loop {
+            let mut length_buf = [0u8; 8]; // Buffer to store incoming data
+
+            if let Err(e) = sock.read_exact(&mut length_buf).await {
+                warn!(error = %e, "failed to read frame length, reconnecting");
+                break;
+            }
+
+            // Convert the 8-byte array to an usize
+            let total_msg_length = usize::from_be_bytes(length_buf);
+
+            // any frame from the broker proves the connection is alive
+            connection_last_seen.insert(conn_number, Instant::now());
+            producer_metrics.connection(conn_number).frames_received.fetch_add(1, Ordering::Relaxed);
+
+            if total_msg_length > 0 {
+
+                // Create a buffer for the remaining part of the message
+                let mut total_buf = vec![0u8; total_msg_length];
+
+                // Read the exact message length data into the buffer
+                if let Err(err) = sock.read_exact(&mut total_buf).await {
+                    warn!(error = %err, frame_length = total_msg_length, "failed to read frame, reconnecting");
+                    break;
+                }
+
+                // Process the full message
+                producer_decode_msg(total_buf, conn_number, &settings).await;
+            }
+        }
+
+        drop(guard);
+        mark_dead(conn_number, settings);
+    }.instrument(info_span!("reader", connection = conn_number)));
+
+    socket_reader_tasks.insert(conn_number, task);
+}
+
+// drops the connection from the pool and hands it over to reconnection
+pub(super) fn mark_dead(conn_number: i32, settings: Arc<ConnectionSettings>) {
+
+    // a closed producer does not come back
+    if producer_closed.load(Ordering::SeqCst) {
+        return;
+    }
+
+    // only one reconnection per connection at a time
+    if reconnecting_connections.insert(conn_number, ()).is_some() {
+        return;
+    }
+
+    pool_socket_writer.remove(&conn_number);
+    pool_socket_reader.remove(&conn_number);
+    connection_last_seen.remove(&conn_number);
+    connection_session_expiry.remove(&conn_number);
+
+    // acks for frames written on the old socket never arrive, their slots go with it and the frames are sent again
+    connection_windows.remove(&conn_number);
+    let unacked_keys: Vec<String> = in_flight_slots.iter()
+        .filter(|slot| slot.value().conn_number == conn_number)
+        .map(|slot| slot.key().to_string())
+        .collect();
+    let unacked = unacked_keys.into_iter().filter_map(|unique_key| in_flight_slots.remove(&unique_key)).collect();
+    resend_unacked(unacked, &settings);
+
+    // control frames its writer kept move to a live connection instead of waiting for this one
+    wake_writer(conn_number);
+
+    if let Some((_, task)) = socket_reader_tasks.remove(&conn_number) {
+        task.abort();
+    }
+
+    tokio::spawn(reconnect(conn_number, settings).instrument(info_span!("reconnect", connection = conn_number)));
+}
+
+async fn reconnect(conn_number: i32, settings: Arc<ConnectionSettings>) {
+    let mut backoff = settings.reconnect_backoff_ms;
+
+    loop {
+        sleep(Duration::from_millis(backoff)).await;
+
+        if producer_closed.load(Ordering::SeqCst) {
+            reconnecting_connections.remove(&conn_number);
+            return;
+        }
+
+        match open_connection(&settings).await {
+            // the producer was closed while the connection was being opened
+            Ok(_) if producer_closed.load(Ordering::SeqCst) => {
+                reconnecting_connections.remove(&conn_number);
+                return;
+            }
+            Ok((conn, session_lifetime)) => {
+                add_to_pool(conn_number, conn, session_lifetime, &settings);
+                info!(connection = conn_number, "reconnected");
+                producer_metrics.connection(conn_number).reconnects.fetch_add(1, Ordering::Relaxed);
+                reconnecting_connections.remove(&conn_number);
+                spawn_socket_reader(conn_number, settings);
+                return;
+            }
+            Err(err) => {
+                warn!(connection = conn_number, error = %err, backoff_ms = backoff, "failed to reconnect");
+                backoff = backoff.saturating_mul(2).min(settings.reconnect_backoff_max_ms);
+            }
+        }
+    }
+}
+
+// pings every pooled connection through its writer and kills the ones the broker stopped answering on
+// queueing never waits on a socket, so a connection stuck behind a full send buffer does not hold up the others
+pub(super) fn spawn_heartbeat(settings: Arc<ConnectionSettings>) {
+    if settings.heartbeat_interval_ms == 0 {
+        return;
+    }
+
+    let task = tokio::spawn(async move {
+        let heartbeat_interval = Duration::from_millis(settings.heartbeat_interval_ms);
+        let heartbeat_timeout = Duration::from_millis(settings.heartbeat_timeout_ms);
+        let deadline = heartbeat_interval + heartbeat_timeout;
+
+        let mut ticker = interval(heartbeat_interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        // the last ping on every connection, so an unanswered one does not stay registered forever
+        let mut outstanding_pings: HashMap<i32, String> = HashMap::new();
+
+        // a throttled connection writes nothing, not even its ping, so the deadline starts over once the throttle ends
+        let mut throttle_ends: HashMap<i32, Instant> = HashMap::new();
+
+        let mut warned_unsupported = false;
+
+        loop {
+            ticker.tick().await;
+
+            let conn_numbers: Vec<i32> = pool_socket_writer.iter().map(|entry| *entry.key()).collect();
+
+            // brokers without heartbeat frames never answer a ping, only connections owing an ack can be judged then
+            let heartbeat_supported = is_supported(MessageCode::Heartbeat);
+            if !heartbeat_supported && !conn_numbers.is_empty() && !warned_unsupported {
+                warn!("broker does not support heartbeats, idle connections are not checked, only ones with unacked frames");
+                warned_unsupported = true;
+            }
+
+            for conn_number in conn_numbers {
+                if let Some(until) = throttled_until(conn_number) {
+                    throttle_ends.insert(conn_number, until);
+                    continue;
+                }
+
+                let last_seen = connection_last_seen.get(&conn_number).map(|entry| *entry.value());
+                let quiet_since = match (last_seen, throttle_ends.get(&conn_number)) {
+                    (Some(last_seen), Some(throttle_end)) => Some(last_seen.max(*throttle_end)),
+                    (last_seen, _) => last_seen,
+                };
+
+                // without heartbeats, silence only counts against a connection that was owed an ack the whole time
+                let judged = heartbeat_supported || oldest_unacked_write(conn_number).map(|written| written.elapsed() > deadline).unwrap_or(false);
+                if let Some(quiet_since) = quiet_since.filter(|_| judged) {
+                    if quiet_since.elapsed() > deadline {
+                        warn!(connection = conn_number, last_seen_ms = quiet_since.elapsed().as_millis() as u64, "connection missed its heartbeat deadline, reconnecting");
+                        throttle_ends.remove(&conn_number);
+                        mark_dead(conn_number, Arc::clone(&settings));
+                        continue;
+                    }
+                }
+
+                let session_expiry = connection_session_expiry.get(&conn_number).map(|entry| *entry.value());
+                if let Some(session_expiry) = session_expiry {
+                    if Instant::now() >= session_expiry {
+                        info!(connection = conn_number, "session is about to expire, reconnecting to authenticate again");
+                        throttle_ends.remove(&conn_number);
+                        mark_dead(conn_number, Arc::clone(&settings));
+                        continue;
+                    }
+                }
+
+                if !heartbeat_supported {
+                    continue;
+                }
+
+                // the pong is routed like a control response, so the reader does not take it for a message ack
+                let unique_key = Uuid::new_v4().to_string();
+                let (responder, _) = oneshot::channel();
+                pending_requests.insert(unique_key.to_string(), responder);
+                if let Some(previous) = outstanding_pings.insert(conn_number, unique_key.to_string()) {
+                    pending_requests.remove(&previous);
+                }
+
+                // behind the frames already queued, so it never lands inside a batch being written
+                // a ping stuck behind a full socket buffer goes unanswered and counts as a missed heartbeat
+                if !queue_control(conn_number, producer_encode_heartbeat(unique_key.to_string())) {
+                    outstanding_pings.remove(&conn_number);
+                    pending_requests.remove(&unique_key);
+                }
+            }
+        }
+    }.instrument(info_span!("heartbeat")));
+
+    if let Some(previous) = producer_tasks.insert("heartbeat".to_string(), task) {
+        previous.abort();
+    }
+}
+
+// when the oldest frame still waiting for its ack on this connection was written
+fn oldest_unacked_write(conn_number: i32) -> Option<Instant> {
+    in_flight_slots.iter()
+        .filter(|slot| slot.value().conn_number == conn_number)
+        .filter_map(|slot| first_written_at.get(slot.key()).map(|written| *written.value()))
+        .min()
+}
+
+fn producer_encode_heartbeat(unique_key: String) -> EncodedFrame {
+    let mut bb = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    // into big endian format
+    bb.init("big".to_string());
+
+    // version number
+    bb.put_string("V_1".to_string());
+
+    // message type either producer or consumer
+    bb.put_string("P".to_string());
+
+    // message code for heartbeat
+    bb.put_int(MessageCode::Heartbeat as i32);
+
+    // put unique key, the broker echoes it back in the response
+    bb.put_string(unique_key);
+
+    // wrapping the message into another byte array to get its total length
+    let mut wrap_byte = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    wrap_byte.put(bb.to_array());
+
+    EncodedFrame {
+        header: Bytes::from(wrap_byte.to_array()),
+        payload: Bytes::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Error, ErrorKind, IoSlice};
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tokio::io::AsyncWrite;
+    use super::write_vectored_all;
+
+    // takes at most chunk bytes per call from the first buffer, like a socket with a full send buffer
+    // and fails once fail_after bytes went through
+    struct ShortWriter {
+        written: Vec<u8>,
+        chunk: usize,
+        fail_after: Option<usize>,
+    }
+
+    impl AsyncWrite for ShortWriter {
+        fn poll_write(self: Pin<&mut Self>, _: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, Error>> {
+            let writer = self.get_mut();
+            let room = match writer.fail_after {
+                Some(limit) if writer.written.len() >= limit => {
+                    return Poll::Ready(Err(Error::new(ErrorKind::BrokenPipe, "connection closed")));
+                }
+                Some(limit) => limit - writer.written.len(),
+                None => usize::MAX,
+            };
+            let n = buf.len().min(writer.chunk).min(room);
+            writer.written.extend_from_slice(&buf[..n]);
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_write_vectored(self: Pin<&mut Self>, cx: &mut Context<'_>, bufs: &[IoSlice<'_>]) -> Poll<Result<usize, Error>> {
+            let buf = bufs.iter().find(|buf| !buf.is_empty()).map(|buf| &buf[..]).unwrap_or(&[]);
+            self.poll_write(cx, buf)
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Error>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_short_write_sends_the_rest_of_every_part() {
+        let mut writer = ShortWriter { written: Vec::new(), chunk: 3, fail_after: None };
+
+        write_vectored_all(&mut writer, &[b"header", b"", b"payload", b"x"]).await.unwrap();
+
+        assert_eq!(writer.written, b"headerpayloadx".to_vec());
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_reports_the_bytes_that_went_out() {
+        let mut writer = ShortWriter { written: Vec::new(), chunk: 4, fail_after: Some(9) };
+
+        let (written, err) = write_vectored_all(&mut writer, &[b"header", b"payload"]).await.unwrap_err();
+
+        assert_eq!(written, 9);
+        assert_eq!(err.kind(), ErrorKind::BrokenPipe);
+        assert_eq!(writer.written, b"headerpay".to_vec());
+    }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use bytes::Bytes;
+use tracing::field::Empty;
+use tracing::instrument;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{buffer_budget, ChannelWriter, Producer, producer_closed, producer_identity, ProducerHandle, ProducerRecord, QueuedMessage, rate_limits};
+
+impl Producer {
+    // everything a push looks up behind a lock is taken once here, clone the handle into as many tasks as needed
+    pub async fn handle(&self) -> Result<ProducerHandle, ProducerError> {
+        if self.transactional_id.is_some() {
+            return Err(ProducerError::InvalidTransactionState("transactional producers have to send inside a transaction".to_string()));
+        }
+
+        let sender = ChannelWriter.read().await.as_ref().cloned();
+        let budget = buffer_budget.read().await.as_ref().cloned();
+        let (sender, budget) = match (sender, budget) {
+            (Some(sender), Some(budget)) => (sender, budget),
+            _ => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        Ok(ProducerHandle {
+            producer: Arc::new(self.clone()),
+            sender,
+            budget,
+            limits: rate_limits.read().await.as_ref().cloned(),
+            identity: *producer_identity.read().await,
+        })
+    }
+}
+
+impl ProducerHandle {
+    pub async fn push(&self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.push_record(ProducerRecord::new(topic, key, Bytes::from(msg))).await
+    }
+
+    pub async fn push_bytes(&self, topic: &str, key: &str, payload: Bytes) -> Result<(), ProducerError> {
+        self.push_record(ProducerRecord::new(topic.to_string(), key.to_string(), payload)).await
+    }
+
+    // waits under the Block policy like Producer::push_record, every other policy never waits
+    #[instrument(name = "push", skip_all, fields(topic = %record.topic, partition = Empty, unique_key = Empty))]
+    pub async fn push_record(&self, record: ProducerRecord) -> Result<(), ProducerError> {
+        let message = self.encode(record)?;
+
+        self.producer.message_queued();
+        if self.producer.backpressure_policy.unwrap_or_default() == BackpressurePolicy::Block {
+            self.producer.enqueue_to(message, &self.sender, &self.budget, self.limits.as_deref()).await
+        } else {
+            self.producer.try_enqueue_to(message, &self.sender, &self.budget, self.limits.as_deref())
+        }
+    }
+
+    // never awaits, a full queue fails or drops a message according to the backpressure policy
+    pub fn try_push(&self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.try_push_record(ProducerRecord::new(topic, key, Bytes::from(msg)))
+    }
+
+    pub fn try_push_bytes(&self, topic: &str, key: &str, payload: Bytes) -> Result<(), ProducerError> {
+        self.try_push_record(ProducerRecord::new(topic.to_string(), key.to_string(), payload))
+    }
+
+    #[instrument(name = "push", skip_all, fields(topic = %record.topic, partition = Empty, unique_key = Empty))]
+    fn try_push_record(&self, record: ProducerRecord) -> Result<(), ProducerError> {
+        let message = self.encode(record)?;
+
+        self.producer.message_queued();
+        self.producer.try_enqueue_to(message, &self.sender, &self.budget, self.limits.as_deref())
+    }
+
+    // the sender outlives close, so the flag stops pushes from a handle taken before it
+    fn encode(&self, record: ProducerRecord) -> Result<QueuedMessage, ProducerError> {
+        if producer_closed.load(Ordering::SeqCst) {
+            return Err(ProducerError::NotConnected);
+        }
+
+        let record = self.producer.intercept(record);
+        self.producer.encode_message(record, self.identity)
+    }
+}
@@ -1,146 +1,626 @@
-use std::fmt::Debug;
 use std::sync::Arc;
-use rust_decimal::prelude::ToPrimitive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use bytes::Bytes;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::time::{timeout_at, Instant};
+use tracing::field::Empty;
+use tracing::{debug, error, info, instrument, warn, Instrument, Span};
 use uuid::Uuid;
+use crate::brahmaputra::byte_buffers::concrete_functions::api_versions::{negotiated_version, ProducerFeature, require_feature};
+use crate::brahmaputra::byte_buffers::concrete_functions::authentication::{Authenticator, PlainAuthenticator, ScramSha256Authenticator};
 use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
-use crate::brahmaputra::byte_buffers::concrete_functions::enums::MessageCode;
-use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{ChannelWriter, pool_socket_reader, pool_socket_writer, Producer, socket_current_conn};
+use crate::brahmaputra::byte_buffers::concrete_functions::compression::CompressionType;
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::{BackpressurePolicy, MessageCode, TransactionState};
+use crate::brahmaputra::byte_buffers::concrete_functions::interceptors::ProducerInterceptor;
+use crate::brahmaputra::byte_buffers::concrete_functions::metrics::ProducerMetrics;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{buffer_budget, ChannelReader, ChannelWriter, connection_last_seen, connection_windows, ControlChannelWriter, ControlFrame, dropped_messages, connection_session_expiry, connection_throttled_until, ConnectionSettings, delivery_waiters, DeliveryOutcome, DeliveryReport, DeliveryResult, EncodedFrame, first_written_at, flush_notify, FrameKind, in_flight_messages, in_flight_slots, InFlightMessage, InFlightSlot, OutgoingFrame, partition_sequences, pending_requests, pool_socket_reader, pool_socket_writer, Producer, producer_closed, producer_compression, producer_identity, producer_live, producer_metrics, producer_tasks, ProducerIdentity, ProducerRecord, queued_frames, QueuedMessage, RetryPolicy, RetryRoute, socket_current_conn, socket_reader_tasks, transaction_partitions, transaction_state, unacked_messages, writer_queues, WriterQueue};
 use crate::brahmaputra::byte_buffers::concrete_functions::select_partition::select_partition;
+use crate::brahmaputra::byte_buffers::concrete_functions::tls::TlsSettings;
+use crate::brahmaputra::byte_buffers::decoders::producers::producer_decode_init_id;
+use crate::brahmaputra::byte_buffers::encoders::connections::{add_to_pool, mark_dead, next_connection, open_connection, pinned_connection, spawn_heartbeat, spawn_socket_reader, throttle_connection, wake_writer};
+use crate::brahmaputra::byte_buffers::encoders::writers::{spawn_writer, try_queue, MAX_BATCH_FRAMES};
+use crate::brahmaputra::byte_buffers::encoders::spill::{close_spill_journal, settle_spilled};
+use crate::brahmaputra::byte_buffers::encoders::transactions::fail_transaction;
 
 impl Producer {
-    pub async fn connect_producer(&mut self) {
+    // the producer state is process wide, so connecting fails while another producer is still connected
+    #[instrument(name = "connect", skip_all, fields(servers = %self.servers))]
+    pub async fn connect_producer(&mut self) -> Result<(), ProducerError> {
+        if producer_live.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_err() {
+            return Err(ProducerError::InvalidConfig("a producer is already connected in this process, close it or share it".to_string()));
+        }
+
+        let connected = self.start_producer().await;
+        if connected.is_err() {
+            producer_live.store(false, Ordering::SeqCst);
+        }
+        connected
+    }
+
+    async fn start_producer(&mut self) -> Result<(), ProducerError> {
+
+        // rejecting bad settings before anything is written to the broker
+        self.validate()?;
 
         // setting current conn to 0
         let _ = socket_current_conn.write().await.insert(0);
-        
-        // Connect to the server and build the connection pool
-        let pool_size = self.pool.unwrap_or(1);
-        for i in 0..pool_size {
-            match TcpStream::connect(self.servers.to_string()).await {
-                Ok(conn) => {
 
-                    let (read_half, write_half) = tokio::io::split(conn);
+        // starting over after a previous close
+        producer_closed.store(false, Ordering::SeqCst);
+        queued_frames.store(0, Ordering::SeqCst);
+        unacked_messages.store(0, Ordering::SeqCst);
+        dropped_messages.store(0, Ordering::SeqCst);
+
+        let settings = Arc::new(self.connection_settings()?);
+        *producer_compression.write().unwrap() = self.compression()?;
 
-                    pool_socket_writer.insert(i, Arc::new(RwLock::new(Some(write_half))));
+        // frames an earlier run spilled to disk go out before anything pushed from now on
+        self.open_spill_journal()?;
 
-                    pool_socket_reader.insert(i, Arc::new(RwLock::new(Some(read_half))));
+        // Connect to the server and build the connection pool
+        let pool_size = settings.pool_size;
+        let mut last_error = None;
+        for i in 0..pool_size {
+            match open_connection(&settings).await {
+                Ok((conn, session_lifetime)) => {
+                    add_to_pool(i, conn, session_lifetime, &settings);
                 }
                 Err(err) => {
-                    println!("Failed to connect to server: {}", err);
+                    warn!(connection = i, error = %err, "failed to open connection");
+                    last_error = Some(err);
                 }
             }
         }
 
+        // connections that failed are reopened in the background, but at least one has to work now
+        if pool_socket_writer.is_empty() {
+            match last_error {
+                // wrong credentials or an old broker will not fix themselves by reconnecting
+                Some(err @ ProducerError::Auth(_)) | Some(err @ ProducerError::UnsupportedVersion(_)) => {
+                    return Err(err);
+                }
+                // with a spill journal messages go to disk until a broker is reachable
+                Some(err) if self.spill_dir.is_some() => {
+                    warn!(error = %err, "no broker is reachable, messages are spilled to disk until one is");
+                }
+                Some(err) => {
+                    return Err(ProducerError::AllBrokersDown(Box::new(err)));
+                }
+                None => {
+                    return Err(ProducerError::NotConnected);
+                }
+            }
+        }
+
+        // failing early when the broker is too old for what the producer was configured to do
+        if self.transactional_id.is_some() {
+            require_feature(ProducerFeature::Transactions)?;
+        } else if self.is_idempotent() {
+            require_feature(ProducerFeature::Idempotence)?;
+        }
+
+        // fetching the producer id before any message frame is written
+        if self.is_idempotent() {
+            self.init_producer_id().await?;
+        }
+
         // creating channel
-        let (tx, mut rx) = mpsc::channel::<Box<Vec<u8>>>(self.max_buffer_size.unwrap_or(100000) as usize);
+        let (tx, rx) = mpsc::channel::<QueuedMessage>(self.max_buffer_size.unwrap_or(100000) as usize);
+        let (control_tx, control_rx) = mpsc::unbounded_channel::<ControlFrame>();
         let _ = ChannelWriter.write().await.insert(tx);
-        let pool_clone = Arc::new(self.pool.unwrap());
-        let pool_clone_copy = Arc::clone(&pool_clone);
+        let _ = ChannelReader.lock().await.insert(rx);
+        let _ = ControlChannelWriter.write().await.insert(control_tx.clone());
+        let _ = buffer_budget.write().await.insert(Arc::new(Semaphore::new(self.buffer_memory_limit())));
+        producer_metrics.set_buffer_memory_limit(self.buffer_memory_limit() as u64);
+        self.install_rate_limits().await;
+
+        // handing queued frames to the writer task of each pooled connection
+        let dispatcher = tokio::spawn(dispatch(control_rx, control_tx, Arc::clone(&settings), self.acks() != "0"));
+
+        if let Some(previous) = producer_tasks.insert("dispatcher".to_string(), dispatcher) {
+            previous.abort();
+        }
+
+        // receives the channels from socket, connections that failed above go straight to reconnection
+        for i in 0..pool_size {
+            if pool_socket_reader.contains_key(&i) {
+                spawn_socket_reader(i, Arc::clone(&settings));
+            } else {
+                mark_dead(i, Arc::clone(&settings));
+            }
+        }
+
+        self.spawn_spill_replay(Arc::clone(&settings));
+        self.spawn_spill_sync();
+
+        // pinging the broker so half open connections get noticed
+        spawn_heartbeat(settings);
+
+        info!(connections = pool_socket_writer.len(), pool_size, "producer connected");
+
+        Ok(())
+    }
+
+    // checks settings that cannot work on their own or together, unset fields count as their defaults
+    pub fn validate(&self) -> Result<(), ProducerError> {
+        if self.servers.trim().is_empty() {
+            return Err(ProducerError::InvalidConfig("servers is required".to_string()));
+        }
+        if !self.servers.contains(':') {
+            return Err(ProducerError::InvalidConfig(format!("servers must be host:port, got {}", self.servers)));
+        }
+
+        self.compression()?;
+
+        if !matches!(self.acks(), "0" | "1" | "all") {
+            return Err(ProducerError::InvalidConfig(format!("acks must be \"0\", \"1\" or \"all\", got {}", self.acks())));
+        }
+
+        if let Some(pool) = self.pool {
+            if !(1..=1024).contains(&pool) {
+                return Err(ProducerError::InvalidConfig(format!("pool must be between 1 and 1024, got {}", pool)));
+            }
+        }
+        if self.max_buffer_size == Some(0) {
+            return Err(ProducerError::InvalidConfig("max_buffer_size must be greater than 0".to_string()));
+        }
+        if self.buffer_memory_bytes == Some(0) {
+            return Err(ProducerError::InvalidConfig("buffer_memory_bytes must be greater than 0".to_string()));
+        }
+        if self.transaction_timeout_ms == Some(0) {
+            return Err(ProducerError::InvalidConfig("transaction_timeout_ms must be greater than 0".to_string()));
+        }
+        if self.spill_segment_bytes == Some(0) {
+            return Err(ProducerError::InvalidConfig("spill_segment_bytes must be greater than 0".to_string()));
+        }
+        if self.spill_max_bytes == Some(0) {
+            return Err(ProducerError::InvalidConfig("spill_max_bytes must be greater than 0".to_string()));
+        }
+        if self.heartbeat_timeout_ms == Some(0) {
+            return Err(ProducerError::InvalidConfig("heartbeat_timeout_ms must be greater than 0".to_string()));
+        }
+
+        self.validate_rate_limits()?;
+
+        let retry_policy = self.retry_policy();
 
-        // starting reader channel
-        tokio::spawn(async move{
-            while let Some(total_buf) = rx.recv().await {
+        if self.max_in_flight_per_connection == Some(0) {
+            return Err(ProducerError::InvalidConfig("max_in_flight_per_connection must be greater than 0".to_string()));
+        }
 
-                let mut conn_number = socket_current_conn.read().await.as_ref().unwrap().to_i32().unwrap();
+        // a retried frame lands behind the frames written after it, only sequence numbers put them back in order
+        if self.max_in_flight_per_connection.unwrap_or(1) > 1 && retry_policy.retries > 0 && self.acks() != "0" && !self.is_idempotent() {
+            return Err(ProducerError::InvalidConfig("retries without idempotence can reorder messages, set max_in_flight_per_connection to 1 or enable idempotence".to_string()));
+        }
+        if retry_policy.retry_backoff_ms > retry_policy.retry_backoff_max_ms {
+            return Err(ProducerError::InvalidConfig("retry_backoff_ms must not be larger than retry_backoff_max_ms".to_string()));
+        }
+        if self.reconnect_backoff_ms.unwrap_or(50) > self.reconnect_backoff_max_ms.unwrap_or(1000) {
+            return Err(ProducerError::InvalidConfig("reconnect_backoff_ms must not be larger than reconnect_backoff_max_ms".to_string()));
+        }
 
-                if conn_number >= pool_clone_copy.to_i32().unwrap() - 1 {
-                    conn_number = 0;
-                } else {
-                    conn_number = conn_number + 1;
+        // an explicit authenticator brings its own credentials
+        if self.authenticator.is_none() {
+            if let Some(mechanism) = self.sasl_mechanism.as_deref() {
+                if matches!(mechanism.to_uppercase().as_str(), "PLAIN" | "SCRAM-SHA-256") && (self.sasl_username.is_none() || self.sasl_password.is_none()) {
+                    return Err(ProducerError::InvalidConfig(format!("{} requires sasl_username and sasl_password", mechanism)));
                 }
+            }
+        }
+
+        // transactions are built on top of the idempotent producer
+        if self.transactional_id.is_some() && self.enable_idempotence == Some(false) {
+            return Err(ProducerError::InvalidConfig("a transactional id requires idempotence to be enabled".to_string()));
+        }
+
+        // spilled frames are replayed on their own, outside of the transaction they were sent in
+        if self.transactional_id.is_some() && self.spill_dir.is_some() {
+            return Err(ProducerError::InvalidConfig("a transactional producer cannot spill messages to disk".to_string()));
+        }
+
+        // spilled frames keep the producer id and sequence they were encoded with, after a restart the broker would reject them
+        if self.is_idempotent() && self.spill_dir.is_some() {
+            return Err(ProducerError::InvalidConfig("an idempotent producer cannot spill messages to disk".to_string()));
+        }
+
+        // idempotence only holds when every replica acks and lost frames are sent again
+        if self.is_idempotent() {
+            if matches!(self.backpressure_policy, Some(BackpressurePolicy::DropOldest) | Some(BackpressurePolicy::DropNewest)) {
+                return Err(ProducerError::InvalidConfig("idempotence cannot drop messages, they would leave gaps in the sequence numbers".to_string()));
+            }
+            if self.acks() != "all" {
+                return Err(ProducerError::InvalidConfig("idempotence requires acks to be \"all\"".to_string()));
+            }
+            if retry_policy.retries == 0 {
+                return Err(ProducerError::InvalidConfig("idempotence requires retries to be greater than 0".to_string()));
+            }
+        }
 
-                if pool_socket_writer.contains_key(&conn_number){
-                    if let socket = pool_socket_writer.get(&conn_number).unwrap(){
-                        match  socket.write().await.as_mut(){
-                            None => {}
-                            Some(sock) => {
-                                match sock.write_all(total_buf.as_slice()).await{
-                                    Ok(_) => {
-                                        match sock.flush().await {
-                                            Err(err) => {
-                                                println!("{:?}", err);
-                                            }
-                                            _ => {}
-                                        }
-                                    }
-                                    Err(err) => {
-                                        println!("{:?}", err);
-                                    }
-                                }
-                            }
-                        }
+        Ok(())
+    }
+
+    // every replica has to ack unless configured otherwise
+    pub(super) fn acks(&self) -> &str {
+        self.acks.as_deref().unwrap_or("all")
+    }
+
+    fn compression(&self) -> Result<CompressionType, ProducerError> {
+        CompressionType::from_name(self.compression_type.as_deref().unwrap_or("none"))
+            .map_err(|err| ProducerError::InvalidConfig(err.to_string()))
+    }
+
+    pub(super) fn is_idempotent(&self) -> bool {
+        self.enable_idempotence.unwrap_or(false) || self.transactional_id.is_some()
+    }
+
+    // frames written on a connection that have not got their final ack yet
+    // without idempotence a retry could overtake the frames behind it, so they wait for it
+    pub(super) fn max_in_flight_per_connection(&self) -> usize {
+        match self.max_in_flight_per_connection {
+            Some(max_in_flight) => max_in_flight as usize,
+            None if !self.is_idempotent() && self.retry_policy().retries > 0 => 1,
+            None => 5,
+        }
+    }
+
+    fn connection_settings(&self) -> Result<ConnectionSettings, ProducerError> {
+
+        // certificates are loaded once up front so a bad path fails connect_producer instead of every reconnect
+        let tls = match &self.tls {
+            Some(tls) => Some(TlsSettings::from_config(tls, &self.servers).map_err(|err| ProducerError::InvalidConfig(format!("tls: {}", err)))?),
+            None => None,
+        };
+
+        let authenticator: Option<Arc<dyn Authenticator>> = match (&self.authenticator, self.sasl_mechanism.as_deref()) {
+            (Some(authenticator), _) => Some(Arc::clone(authenticator)),
+            (None, None) => None,
+            (None, Some(mechanism)) => {
+                let username = self.sasl_username.clone().unwrap_or_default();
+                let password = self.sasl_password.clone().unwrap_or_default();
+
+                match mechanism.to_uppercase().as_str() {
+                    "PLAIN" => Some(Arc::new(PlainAuthenticator { username, password })),
+                    "SCRAM-SHA-256" => Some(Arc::new(ScramSha256Authenticator { username, password })),
+                    "OAUTHBEARER" => {
+                        return Err(ProducerError::InvalidConfig("OAUTHBEARER needs a BearerTokenAuthenticator set as the authenticator".to_string()));
+                    }
+                    _ => {
+                        return Err(ProducerError::InvalidConfig(format!("unsupported SASL mechanism: {}", mechanism)));
                     }
                 }
             }
-        });
-
-        // receives the channels from socket
-        tokio::spawn(async move{
-            for i in 0..pool_size {
-                let count = i;
-                tokio::spawn(async move {
-                    loop {
-                        if pool_socket_reader.contains_key(&count){
-
-                            if let socket = pool_socket_reader.get(&count).unwrap(){
-
-                                let mut length_buf = [0u8; 8]; // Buffer to store incoming data
-
-                                match socket.write().await.as_mut(){
-                                    None => {}
-                                    Some(sock) => {
-                                        match sock.read_exact(&mut length_buf).await {
-
-                                            Ok(_) => {
-
-                                                // Convert the 8-byte array to an usize
-                                                let total_msg_length = usize::from_be_bytes(length_buf);
-
-                                                if total_msg_length > 0 {
-
-                                                    // Create a buffer for the remaining part of the message
-                                                    let mut total_buf = Box::new(vec![0u8; total_msg_length]);
-
-                                                    // Read the exact message length data into the buffer
-                                                    match sock.read_exact(&mut total_buf).await {
-                                                        Ok(_) => {
-                                                            // Process the full message
-                                                            producer_decode_msg(total_buf).await;
-                                                        }
-                                                        Err(err) => {
-                                                            println!("Error reading message data: {}", err);
-                                                            //remove_socket_client(producer_id);
-                                                            break;
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            Err(e) => {
-                                                eprintln!("Failed to read from socket; err = {:?}", e);
-                                                break;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-
-                        }
-                    }
-                });
+        };
+
+        Ok(ConnectionSettings {
+            servers: self.servers.to_string(),
+            pool_size: self.pool.unwrap_or(1).max(1),
+            reconnect_backoff_ms: self.reconnect_backoff_ms.unwrap_or(50),
+            reconnect_backoff_max_ms: self.reconnect_backoff_max_ms.unwrap_or(1000),
+            heartbeat_interval_ms: self.heartbeat_interval_ms.unwrap_or(3000),
+            heartbeat_timeout_ms: self.heartbeat_timeout_ms.unwrap_or(10000),
+            retry_policy: self.retry_policy(),
+            max_in_flight_per_connection: self.max_in_flight_per_connection(),
+            // room for a full batch or a full window, whichever is more, but never more than the producer queue
+            writer_queue_size: self.max_in_flight_per_connection().max(MAX_BATCH_FRAMES).min(self.max_buffer_size.unwrap_or(100000) as usize),
+            tls,
+            authenticator,
+            interceptors: self.interceptors.clone(),
+        })
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.retries.unwrap_or(0),
+            retry_backoff_ms: self.retry_backoff_ms.unwrap_or(100),
+            retry_backoff_max_ms: self.retry_backoff_max_ms.unwrap_or(1000),
+        }
+    }
+
+    // connections that are still coming up have not negotiated yet, v1 is understood by every broker
+    fn produce_version(&self) -> Result<i16, ProducerError> {
+        match negotiated_version(MessageCode::ProducerMsg) {
+            Ok(version) => Ok(version),
+            Err(ProducerError::NotConnected) if !self.is_idempotent() => Ok(1),
+            Err(err) => Err(err),
+        }
+    }
+
+    // Encode the message with the highest produce version both sides support
+    pub(super) fn encode_message(&self, record: ProducerRecord, identity: Option<ProducerIdentity>) -> Result<QueuedMessage, ProducerError> {
+        if self.produce_version()? >= 2 {
+            let partition = select_partition(record.key.to_string(), 5);
+            self.producer_encode_msg_v2(record, partition, identity)
+        } else {
+            self.producer_encode_msg_v1(record)
+        }
+    }
+
+    pub fn add_interceptor(&mut self, interceptor: Arc<dyn ProducerInterceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    // runs every interceptor over the message before anything is encoded
+    pub(super) fn intercept(&self, mut record: ProducerRecord) -> ProducerRecord {
+        for interceptor in &self.interceptors {
+            interceptor.on_send(&mut record);
+        }
+
+        record
+    }
+
+    // frames are only kept for a retry when the broker is going to ack them
+    pub(super) fn tracks_in_flight(&self) -> bool {
+        self.is_idempotent() || (self.retries.unwrap_or(0) > 0 && self.acks() != "0")
+    }
+
+    async fn init_producer_id(&self) -> Result<(), ProducerError> {
+
+        // any pooled connection can hand out the producer id
+        let conn_number = match pool_socket_writer.iter().map(|entry| *entry.key()).min() {
+            Some(conn_number) => conn_number,
+            None => {
+                return Err(ProducerError::NotConnected);
             }
-        });
+        };
+
+        let writer = pool_socket_writer.get(&conn_number).map(|socket| socket.value().clone());
+        let reader = pool_socket_reader.get(&conn_number).map(|socket| socket.value().clone());
+        let (writer, reader) = match (writer, reader) {
+            (Some(writer), Some(reader)) => (writer, reader),
+            _ => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        let mut bb = ByteBuff {
+            multiplier: 10000.0,
+            endian: "big".to_string(),
+            ..Default::default()
+        };
+
+        bb.init("big".to_string());
+
+        // version number
+        bb.put_string("V_1".to_string());
+
+        // message type either producer or consumer
+        bb.put_string("P".to_string());
+
+        // message code for producer id
+        bb.put_int(MessageCode::InitProducerId as i32);
+
+        // put unique key
+        bb.put_string(Uuid::new_v4().to_string());
+
+        // transactional id, the broker bumps the epoch and fences older producers using the same id
+        bb.put_string(self.transactional_id.clone().unwrap_or_default());
+
+        // transaction timeout
+        bb.put_long(self.transaction_timeout_ms.unwrap_or(60000) as i64);
+
+        let mut wrap_byte = ByteBuff {
+            multiplier: 10000.0,
+            endian: "big".to_string(),
+            ..Default::default()
+        };
+
+        wrap_byte.put(bb.to_array());
+
+        if let Some(sock) = writer.write().await.as_mut() {
+            sock.write_all(wrap_byte.to_array().as_slice()).await?;
+            sock.flush().await?;
+        }
+
+        let mut guard = reader.write().await;
+        let sock = match guard.as_mut() {
+            Some(sock) => sock,
+            None => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        let mut length_buf = [0u8; 8];
+        sock.read_exact(&mut length_buf).await?;
+
+        let mut total_buf = vec![0u8; usize::from_be_bytes(length_buf)];
+        sock.read_exact(&mut total_buf).await?;
+
+        let identity = producer_decode_init_id(total_buf)?;
+        let _ = producer_identity.write().await.insert(identity);
+
+        // sequences restart from 0 for every new producer id
+        partition_sequences.clear();
+        in_flight_messages.clear();
+
+        if self.transactional_id.is_some() {
+            transaction_partitions.clear();
+            *transaction_state.write().await = TransactionState::Ready;
+        }
+
+        Ok(())
+    }
+
+    pub async fn push(&mut self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.push_record(ProducerRecord::new(topic, key, Bytes::from(msg))).await
+    }
+
+    // the payload is written to the socket straight from this buffer, cloning a Bytes to push it again copies nothing
+    pub async fn push_bytes(&mut self, topic: &str, key: &str, payload: Bytes) -> Result<(), ProducerError> {
+        self.push_record(ProducerRecord::new(topic.to_string(), key.to_string(), payload)).await
+    }
+
+    // the message outlives the call, so a borrowed payload is copied once into the buffer it is written from
+    pub async fn push_slice(&mut self, topic: &str, key: &str, payload: &[u8]) -> Result<(), ProducerError> {
+        self.push_record(ProducerRecord::new(topic.to_string(), key.to_string(), Bytes::copy_from_slice(payload))).await
+    }
+
+    // a record can carry headers of its own, interceptors still run over it
+    #[instrument(name = "push", skip_all, fields(topic = %record.topic, partition = Empty, unique_key = Empty))]
+    pub async fn push_record(&mut self, record: ProducerRecord) -> Result<(), ProducerError> {
+        // transactional producers can only write inside a transaction
+        if self.transactional_id.is_some() {
+            return self.send_record(record).await;
+        }
+
+        let identity = *producer_identity.read().await;
+
+        let record = self.intercept(record);
+        let message = self.encode_message(record, identity)?;
+        self.enqueue_message(message).await
+    }
+
+    // returns once the broker acked the message, or with the error the producer gave up on it with
+    pub async fn push_and_wait(&mut self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        let delivery = self.push_tracked(topic, key, Bytes::from(msg)).await?;
+        delivery.await.unwrap_or(Err(ProducerError::NotConnected))
+    }
+
+    // queues the message and hands back a receiver for its final outcome
+    #[instrument(name = "push", skip_all, fields(topic = %topic, partition = Empty, unique_key = Empty))]
+    pub(crate) async fn push_tracked(&mut self, topic: String, key: String, payload: Bytes) -> Result<oneshot::Receiver<DeliveryResult>, ProducerError> {
+        if self.transactional_id.is_some() {
+            return Err(ProducerError::InvalidTransactionState("transactional messages are only delivered when the transaction commits".to_string()));
+        }
+
+        let identity = *producer_identity.read().await;
+
+        let record = self.intercept(ProducerRecord::new(topic, key, payload));
+        let message = self.encode_message(record, identity)?;
+        let (sender, receiver) = oneshot::channel();
+
+        // acks 0 never gets an answer, the message counts as delivered once it is queued
+        if self.acks() == "0" {
+            self.enqueue_message(message).await?;
+            let _ = sender.send(Ok(()));
+            return Ok(receiver);
+        }
+
+        // registered before queueing so an early ack finds the waiter
+        delivery_waiters.insert(message.unique_key.to_string(), sender);
+        self.enqueue_message(message).await?;
+
+        Ok(receiver)
+    }
+
+    // never awaits, a full queue fails or drops a message according to the backpressure policy
+    pub fn try_push(&mut self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.try_push_payload(topic, key, Bytes::from(msg))
     }
 
-    pub async fn push(&mut self, topic: String, key: String, msg: Vec<u8>) {
-        // Encode the message using the producer_encode_msg_v1 method
-        let message_byte = self.producer_encode_msg_v1(topic, key, msg).await;
-        let _ = ChannelWriter.write().await.as_mut().unwrap().send(message_byte).await.is_ok();
+    pub fn try_push_bytes(&mut self, topic: &str, key: &str, payload: Bytes) -> Result<(), ProducerError> {
+        self.try_push_payload(topic.to_string(), key.to_string(), payload)
     }
 
-    async fn producer_encode_msg_v1(&self, topic: String, key: String, msg: Vec<u8>) -> Box<Vec<u8>> {
+    #[instrument(name = "push", skip_all, fields(topic = %topic, partition = Empty, unique_key = Empty))]
+    fn try_push_payload(&mut self, topic: String, key: String, payload: Bytes) -> Result<(), ProducerError> {
+        if self.transactional_id.is_some() {
+            return Err(ProducerError::InvalidTransactionState("transactional producers have to send inside a transaction".to_string()));
+        }
+
+        let identity = match producer_identity.try_read() {
+            Ok(identity) => *identity,
+            // the producer id is being assigned
+            Err(_) => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        let record = self.intercept(ProducerRecord::new(topic, key, payload));
+        let message = self.encode_message(record, identity)?;
+        self.try_enqueue_message(message)
+    }
+
+    // counters, gauges and ack latencies, render_prometheus turns them into a scrape
+    pub fn metrics(&self) -> &'static ProducerMetrics {
+        &producer_metrics
+    }
+
+    // messages dropped by the DropOldest and DropNewest policies since the producer connected
+    pub fn dropped_messages(&self) -> u64 {
+        dropped_messages.load(Ordering::SeqCst)
+    }
+
+    // waits until every queued message is written and acked
+    pub async fn flush(&mut self, timeout: Duration) -> Result<(), ProducerError> {
+        if wait_until_settled(Instant::now() + timeout).await {
+            return Ok(());
+        }
+
+        Err(ProducerError::Timeout(format!(
+            "flushing, {} frames still queued and {} messages waiting for acks",
+            queued_frames.load(Ordering::SeqCst),
+            unacked_messages.load(Ordering::SeqCst)
+        )))
+    }
+
+    // flushes, stops every background task and closes the pooled sockets
+    pub async fn close(&mut self, timeout: Duration) -> Result<(), ProducerError> {
+        let flushed = self.flush(timeout).await;
+
+        producer_closed.store(true, Ordering::SeqCst);
+
+        // no new frames can be queued once the senders are gone
+        let _ = ChannelWriter.write().await.take();
+        let _ = ControlChannelWriter.write().await.take();
+
+        let task_names: Vec<String> = producer_tasks.iter().map(|entry| entry.key().to_string()).collect();
+        for task_name in task_names {
+            if let Some((_, task)) = producer_tasks.remove(&task_name) {
+                task.abort();
+            }
+        }
+
+        let conn_numbers: Vec<i32> = socket_reader_tasks.iter().map(|entry| *entry.key()).collect();
+        for conn_number in conn_numbers {
+            if let Some((_, task)) = socket_reader_tasks.remove(&conn_number) {
+                task.abort();
+            }
+        }
+
+        let conn_numbers: Vec<i32> = pool_socket_writer.iter().map(|entry| *entry.key()).collect();
+        for conn_number in conn_numbers {
+            if let Some((_, socket)) = pool_socket_writer.remove(&conn_number) {
+                if let Some(mut sock) = socket.write().await.take() {
+                    let _ = sock.shutdown().await;
+                }
+            }
+        }
+
+        // messages left behind by a flush that timed out are dropped with the receiver
+        let _ = ChannelReader.lock().await.take();
+
+        // spilled frames without an ack stay on disk for the next connect
+        close_spill_journal().await;
+
+        pool_socket_reader.clear();
+        connection_last_seen.clear();
+        connection_throttled_until.clear();
+        connection_windows.clear();
+        writer_queues.clear();
+        in_flight_slots.clear();
+        first_written_at.clear();
+        connection_session_expiry.clear();
+        pending_requests.clear();
+        delivery_waiters.clear();
+
+        producer_live.store(false, Ordering::SeqCst);
+
+        flushed
+    }
+
+    fn producer_encode_msg_v1(&self, record: ProducerRecord) -> Result<QueuedMessage, ProducerError> {
+        // v1 has no room for headers, this fails with the versions the broker supports
+        if !record.headers.is_empty() {
+            require_feature(ProducerFeature::Headers)?;
+        }
+
+        let compression = *producer_compression.read().unwrap();
+
         let mut bb = ByteBuff {
             multiplier: 10000.0,
             endian: "big".to_string(),
@@ -154,7 +634,7 @@ impl Producer {
         bb.put_string("V_1".to_string());
 
         // topic
-        bb.put_string(topic);
+        bb.put_string(record.topic.to_string());
 
         // message type either producer or consumer
         bb.put_string("P".to_string());
@@ -163,43 +643,337 @@ impl Producer {
         bb.put_int(MessageCode::ProducerMsg as i32); // 100 for messages push
 
         // if there is any compression
-        bb.put_string(self.compression_type.as_ref().unwrap().to_string());
+        bb.put_string(compression.name().to_string());
 
         // acks
-        bb.put_string(self.acks.as_ref().unwrap().to_string());
+        bb.put_string(self.acks().to_string());
 
         // partition
-        let partition = select_partition(key.to_string(), 5);
+        let partition = select_partition(record.key.to_string(), 5);
 
         // put partition
         bb.put_int(partition as i32);
 
         // put unique key
-        bb.put_string(Uuid::new_v4().to_string());
+        let unique_key = Uuid::new_v4().to_string();
+        bb.put_string(unique_key.to_string());
+        Span::current().record("partition", partition).record("unique_key", unique_key.as_str());
 
         // key
-        bb.put_string(key);
+        bb.put_string(record.key.to_string());
+
+        // message, compressed with the codec advertised above, it follows the header as it is
+        let frame = encoded_frame(bb, compressed_payload(compression, record.payload)?);
 
-        // message
-        bb.put(msg);
+        // keeping the frame until it is acked so a retriable error can send it again
+        if self.tracks_in_flight() {
+            in_flight_messages.insert(unique_key.to_string(), InFlightMessage {
+                topic: record.topic.to_string(),
+                partition,
+                key: record.key.to_string(),
+                sequence: -1,
+                frame: frame.clone(),
+                attempts: 0,
+                permit: None,
+                created_at: std::time::Instant::now(),
+            });
+        }
 
-        // converting the total message into byte array
-        let total_msg = bb.to_array();
+        Ok(QueuedMessage {
+            unique_key,
+            topic: record.topic,
+            partition,
+            key: record.key,
+            frame,
+            permit: None,
+        })
+    }
 
-        // wrapping the message into another byte array to get its total length
-        let mut wrap_byte = ByteBuff {
+    // v3 is v2 with headers, records without headers keep the v2 layout so older brokers still take them
+    pub(super) fn producer_encode_msg_v2(&self, record: ProducerRecord, partition: u32, identity: Option<ProducerIdentity>) -> Result<QueuedMessage, ProducerError> {
+        let with_headers = !record.headers.is_empty();
+        if with_headers {
+            require_feature(ProducerFeature::Headers)?;
+        }
+
+        let compression = *producer_compression.read().unwrap();
+
+        // producers without idempotence send -1 so the broker skips the sequence checks
+        let identity = match identity {
+            Some(identity) if self.is_idempotent() => identity,
+            None if self.is_idempotent() => {
+                return Err(ProducerError::NotConnected);
+            }
+            _ => ProducerIdentity {
+                producer_id: -1,
+                producer_epoch: -1,
+            },
+        };
+
+        let mut bb = ByteBuff {
             multiplier: 10000.0,
             endian: "big".to_string(),
             ..Default::default()
         };
 
-        wrap_byte.put(total_msg);
+        // into big endian format
+        bb.init("big".to_string());
+
+        // version number, v2 carries the producer id and sequence number
+        bb.put_string(if with_headers { "V_3" } else { "V_2" }.to_string());
+
+        // topic
+        bb.put_string(record.topic.to_string());
+
+        // message type either producer or consumer
+        bb.put_string("P".to_string());
+
+        // message code for producer
+        bb.put_int(MessageCode::ProducerMsg as i32);
+
+        // if there is any compression
+        bb.put_string(compression.name().to_string());
+
+        // acks
+        bb.put_string(self.acks().to_string());
+
+        // put partition
+        bb.put_int(partition as i32);
+
+        // producer id and epoch
+        bb.put_long(identity.producer_id);
+        bb.put_short(identity.producer_epoch);
+
+        // sequence number, monotonically increasing per topic partition
+        let sequence = if !self.is_idempotent() {
+            -1
+        } else {
+            let mut next_sequence = partition_sequences.entry((record.topic.to_string(), partition)).or_insert(0);
+            let sequence = *next_sequence;
+            *next_sequence = sequence.wrapping_add(1);
+            sequence
+        };
+        bb.put_int(sequence);
+
+        // transactional frames stay invisible to consumers until the transaction commits
+        bb.put_bool(self.transactional_id.is_some());
+
+        // put unique key, the broker echoes it back in the ack
+        let unique_key = Uuid::new_v4().to_string();
+        bb.put_string(unique_key.to_string());
+        Span::current().record("partition", partition).record("unique_key", unique_key.as_str());
+
+        // key
+        bb.put_string(record.key.to_string());
+
+        // headers, count then name and value for each, values are not compressed
+        if with_headers {
+            bb.put_int(record.headers.len() as i32);
+            for (name, value) in record.headers {
+                bb.put_string(name);
+                bb.put(value);
+            }
+        }
+
+        // message, compressed with the codec advertised above, it follows the header as it is
+        let frame = encoded_frame(bb, compressed_payload(compression, record.payload)?);
+
+        // keeping the frame until it is acked so it can be sent again with the same sequence
+        if self.tracks_in_flight() {
+            in_flight_messages.insert(unique_key.to_string(), InFlightMessage {
+                topic: record.topic.to_string(),
+                partition,
+                key: record.key.to_string(),
+                sequence,
+                frame: frame.clone(),
+                attempts: 0,
+                permit: None,
+                created_at: std::time::Instant::now(),
+            });
+        }
+
+        Ok(QueuedMessage {
+            unique_key,
+            topic: record.topic,
+            partition,
+            key: record.key,
+            frame,
+            permit: None,
+        })
+    }
+}
+
+// an uncompressed payload is kept as it is, it is only ever written from the caller's buffer
+fn compressed_payload(compression: CompressionType, payload: Bytes) -> Result<Bytes, ProducerError> {
+    match compression {
+        CompressionType::None => Ok(payload),
+        codec => codec.compress(&payload).map(Bytes::from).map_err(|err| ProducerError::Encode(err.to_string())),
+    }
+}
+
+// finishes the header with the payload length and puts the total length in front, the payload is not copied into it
+fn encoded_frame(mut bb: ByteBuff, payload: Bytes) -> EncodedFrame {
+    bb.put_long(payload.len() as i64);
+    let header = bb.to_array();
+
+    // wrapping the header into another byte array, the total length covers the payload behind it
+    let mut wrap_byte = ByteBuff {
+        multiplier: 10000.0,
+        endian: "big".to_string(),
+        ..Default::default()
+    };
+
+    wrap_byte.put_long((header.len() + payload.len()) as i64);
+    wrap_byte.buffer.write_bytes(&header);
+
+    EncodedFrame {
+        header: Bytes::from(wrap_byte.to_array()),
+        payload,
+    }
+}
+
+// control frames and retries skip the message queue so backpressure never holds them back
+pub(super) async fn enqueue_frame(frame: EncodedFrame, retry: Option<RetryRoute>) -> Result<(), ProducerError> {
+    let sender = match ControlChannelWriter.read().await.as_ref() {
+        Some(sender) => sender.clone(),
+        None => {
+            return Err(ProducerError::NotConnected);
+        }
+    };
+
+    queued_frames.fetch_add(1, Ordering::SeqCst);
 
-        Box::new(wrap_byte.to_array())
+    if sender.send(ControlFrame { frame, retry }).is_err() {
+        frame_written();
+        return Err(ProducerError::NotConnected);
     }
+
+    Ok(())
+}
+
+async fn next_queued_message() -> Option<QueuedMessage> {
+    ChannelReader.lock().await.as_mut()?.recv().await
 }
 
-async fn producer_decode_msg(total_buf: Box<Vec<u8>>){
+// sends queued messages and retries to the writer of the connection their partition is pinned to, control frames to any live one
+// it never touches a socket, every connection's writer task writes on its own
+async fn dispatch(mut control_rx: UnboundedReceiver<ControlFrame>, control_tx: UnboundedSender<ControlFrame>, settings: Arc<ConnectionSettings>, takes_slots: bool) {
+    let queues: Vec<Arc<WriterQueue>> = (0..settings.pool_size)
+        .map(|conn_number| spawn_writer(conn_number, control_tx.clone(), Arc::clone(&settings), takes_slots))
+        .collect();
+
+    // a message whose writer has no room, the queue behind it fills up while retries and control frames keep going
+    let mut blocked: Option<(usize, OutgoingFrame)> = None;
+
+    loop {
+        let full_queue = blocked.as_ref().map(|(conn_number, _)| Arc::clone(&queues[*conn_number]));
+
+        tokio::select! {
+            biased;
+            Some(control) = control_rx.recv() => {
+                let (conn_number, outgoing) = match control.retry {
+                    Some(route) => (pinned_connection(&route.topic, route.partition, settings.pool_size), OutgoingFrame {
+                        topic: route.topic,
+                        partition: route.partition,
+                        key: route.key,
+                        unique_key: route.unique_key,
+                        frame: control.frame,
+                        permit: None,
+                        kind: FrameKind::Retry,
+                        queued_at: std::time::Instant::now(),
+                    }),
+                    None => (next_connection(&settings).await.0, OutgoingFrame {
+                        topic: String::new(),
+                        partition: 0,
+                        key: String::new(),
+                        unique_key: String::new(),
+                        frame: control.frame,
+                        permit: None,
+                        kind: FrameKind::Control,
+                        queued_at: std::time::Instant::now(),
+                    }),
+                };
+                let _ = try_queue(&queues[conn_number as usize], outgoing);
+            }
+            _ = wait_for_room(full_queue.as_deref()), if full_queue.is_some() => {
+                if let Some((conn_number, outgoing)) = blocked.take() {
+                    blocked = try_queue(&queues[conn_number], outgoing).map(|outgoing| (conn_number, outgoing));
+                }
+            }
+            Some(message) = next_queued_message(), if blocked.is_none() => {
+                let conn_number = pinned_connection(&message.topic, message.partition, settings.pool_size) as usize;
+                let outgoing = OutgoingFrame {
+                    topic: message.topic,
+                    partition: message.partition,
+                    key: message.key,
+                    unique_key: message.unique_key,
+                    frame: message.frame,
+                    permit: message.permit,
+                    kind: FrameKind::Message,
+                    queued_at: std::time::Instant::now(),
+                };
+                blocked = try_queue(&queues[conn_number], outgoing).map(|outgoing| (conn_number, outgoing));
+            }
+            else => {
+                return;
+            }
+        }
+    }
+}
+
+async fn wait_for_room(queue: Option<&WriterQueue>) {
+    if let Some(queue) = queue {
+        queue.room.notified().await;
+    }
+}
+
+// false when the deadline passed before every queued message was written and acked
+pub(super) async fn wait_until_settled(deadline: Instant) -> bool {
+    loop {
+        // registering before checking so a notification in between is not missed
+        let notified = flush_notify.notified();
+
+        if queued_frames.load(Ordering::SeqCst) == 0 && unacked_messages.load(Ordering::SeqCst) == 0 {
+            return true;
+        }
+
+        if timeout_at(deadline, notified).await.is_err() {
+            return false;
+        }
+    }
+}
+
+fn release_slot(unique_key: &str) {
+    if let Some((_, slot)) = in_flight_slots.remove(unique_key) {
+        wake_writer(slot.conn_number);
+    }
+}
+
+pub(super) fn frame_written() {
+    decrement_and_notify(&queued_frames);
+}
+
+// the message got its final ack, successful or not
+pub(super) fn message_settled() {
+    decrement_and_notify(&unacked_messages);
+}
+
+// wakes up whoever waits on this message in push_and_wait
+pub(super) fn message_delivered(unique_key: &str, result: DeliveryResult) {
+    if let Some((_, waiter)) = delivery_waiters.remove(unique_key) {
+        let _ = waiter.send(result);
+    }
+}
+
+fn decrement_and_notify(counter: &AtomicUsize) {
+    let previous = counter.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| Some(count.saturating_sub(1)));
+    if previous.unwrap_or(0) <= 1 {
+        flush_notify.notify_waiters();
+    }
+}
+
+#[instrument(name = "ack", skip_all, fields(connection = conn_number, topic = Empty, partition = Empty, unique_key = Empty))]
+pub(super) async fn producer_decode_msg(total_buf: Vec<u8>, conn_number: i32, settings: &ConnectionSettings){
 
     let mut bb = Box::new(ByteBuff{
         multiplier: 10000.0,
@@ -207,13 +981,10 @@ async fn producer_decode_msg(total_buf: Box<Vec<u8>>){
         ..Default::default()
     });
 
-    bb.wrap(total_buf.to_vec());
-
-    // deallocating the box
-    drop(total_buf);
+    bb.wrap(total_buf);
 
     // putting as P
-    let client_type = bb.get_string();
+    let _client_type = bb.get_string();
 
     // putting Error Code
     let error_code = bb.get_int();
@@ -233,5 +1004,180 @@ async fn producer_decode_msg(total_buf: Box<Vec<u8>>){
     // putting key
     let key = bb.get_string();
 
-    println!("{}", error_msg);
+    // brokers enforcing quotas append how long this connection should pause, older ones stop after the key
+    if bb.remaining() >= 4 {
+        let throttle_time_ms = bb.get_int();
+        if throttle_time_ms > 0 {
+            throttle_connection(conn_number, Duration::from_millis(throttle_time_ms as u64));
+        }
+    }
+
+    Span::current().record("topic", topic.as_str()).record("partition", partition).record("unique_key", unique_key.as_str());
+
+    // control requests are waiting on their own response
+    if let Some((_, responder)) = pending_requests.remove(&unique_key) {
+        let _ = responder.send((error_code, error_msg));
+        return;
+    }
+
+    let error = BrokerError::from_code(error_code);
+
+    let mut report = DeliveryReport::new(&topic, partition as u32, &key, &unique_key, DeliveryOutcome::Acked, error_msg);
+
+    match error {
+        // a duplicate means the broker already has this sequence, so it counts as delivered
+        None | Some(BrokerError::DuplicateSequenceNumber) => {
+            in_flight_messages.remove(&report.unique_key);
+            message_finished(&report, &settings.interceptors);
+            message_settled();
+        }
+        Some(err) if err.is_fenced() => {
+            // a newer instance with the same transactional id has taken over
+            *transaction_state.write().await = TransactionState::Fenced;
+            in_flight_messages.remove(&report.unique_key);
+            report.outcome = DeliveryOutcome::Rejected(err);
+            message_finished(&report, &settings.interceptors);
+            message_settled();
+            error!(error = %err, error_msg = %report.error_msg, "producer fenced by a newer instance with the same transactional id");
+        }
+        Some(err) if err.is_retriable() => {
+            resend_in_flight(report, err, settings).await;
+        }
+        Some(err) => {
+            in_flight_messages.remove(&report.unique_key);
+            report.outcome = DeliveryOutcome::Rejected(err);
+            message_finished(&report, &settings.interceptors);
+            message_settled();
+            fail_transaction().await;
+            error!(error = %err, error_msg = %report.error_msg, "broker rejected message");
+        }
+    }
+}
+
+async fn resend_in_flight(mut report: DeliveryReport, err: BrokerError, settings: &ConnectionSettings) {
+    let retry_policy = settings.retry_policy;
+
+    let retry = match in_flight_messages.get_mut(&report.unique_key) {
+        Some(mut message) if message.attempts < retry_policy.retries => {
+            message.attempts += 1;
+            Some((message.frame.clone(), message.attempts))
+        }
+        Some(_) => None,
+        None => {
+            // frames that are not kept cannot be sent again
+            report.outcome = DeliveryOutcome::Rejected(err);
+            message_finished(&report, &settings.interceptors);
+            message_settled();
+            fail_transaction().await;
+            error!(error = %err, error_msg = %report.error_msg, "message failed and its frame was not kept for a retry");
+            return;
+        }
+    };
+
+    let (frame, attempts) = match retry {
+        Some(retry) => retry,
+        None => {
+            in_flight_messages.remove(&report.unique_key);
+            report.outcome = DeliveryOutcome::Rejected(err);
+            message_finished(&report, &settings.interceptors);
+            message_settled();
+            fail_transaction().await;
+            error!(error = %err, error_msg = %report.error_msg, retries = retry_policy.retries, "giving up on message");
+            return;
+        }
+    };
+
+    producer_metrics.topic(&report.topic).retries.fetch_add(1, Ordering::Relaxed);
+
+    // exponential backoff, the reader task keeps reading acks while the retry waits
+    let backoff = retry_policy.retry_backoff_ms
+        .saturating_mul(1u64 << (attempts - 1).min(16))
+        .min(retry_policy.retry_backoff_max_ms);
+
+    debug!(error = %err, error_msg = %report.error_msg, attempt = attempts, backoff_ms = backoff, "retrying message");
+
+    // the retry goes back to the connection the partition is pinned to, and keeps its slot there
+    let route = RetryRoute {
+        topic: report.topic.to_string(),
+        partition: report.partition as u32,
+        key: report.key.to_string(),
+        unique_key: report.unique_key.to_string(),
+    };
+
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(backoff)).await;
+        if let Err(err) = enqueue_frame(frame, Some(route)).await {
+            warn!(error = %err, "failed to queue retry");
+        }
+    }.in_current_span());
+}
+
+// frames written on a connection that died were never acked, the kept ones go again in sequence order
+// and do not count as an attempt, the broker never saw them fail
+pub(super) fn resend_unacked(slots: Vec<(String, InFlightSlot)>, settings: &ConnectionSettings) {
+    let mut unacked = Vec::new();
+    for (unique_key, slot) in slots {
+        match in_flight_messages.get(&unique_key) {
+            Some(message) => unacked.push((message.topic.to_string(), message.partition, message.sequence, message.created_at, message.frame.clone(), message.key.to_string(), unique_key.to_string())),
+            None => {
+                // frames that are not kept cannot be sent again, nothing would settle them otherwise
+                let report = DeliveryReport::new(&slot.topic, slot.partition, &slot.key, &unique_key, DeliveryOutcome::Failed, "connection died before the ack".to_string());
+                message_finished(&report, &settings.interceptors);
+                message_settled();
+                warn!(unique_key = %unique_key, "connection died before the message was acked and its frame was not kept for a retry");
+            }
+        }
+    }
+
+    if unacked.is_empty() {
+        return;
+    }
+
+    unacked.sort_by(|a, b| (&a.0, a.1, a.2, a.3).cmp(&(&b.0, b.1, b.2, b.3)));
+
+    tokio::spawn(async move {
+        for (topic, partition, _, _, frame, key, unique_key) in unacked {
+            if let Err(err) = enqueue_frame(frame, Some(RetryRoute { topic, partition, key, unique_key })).await {
+                warn!(error = %err, "failed to queue unacked frame again");
+            }
+        }
+    }.in_current_span());
+}
+
+// every message ends here exactly once, acked, rejected, dropped, written with acks 0 or lost with its connection
+// interceptors, metrics and push_and_wait all hear the same outcome, never about attempts that are retried
+// runs before the message counts as settled, so a flush that returns sees the spill journal cleaned up
+pub(super) fn message_finished(report: &DeliveryReport, interceptors: &[Arc<dyn ProducerInterceptor>]) {
+    let topic_metrics = producer_metrics.topic(&report.topic);
+    match report.outcome {
+        DeliveryOutcome::Acked => {
+            topic_metrics.messages_acked.fetch_add(1, Ordering::Relaxed);
+        }
+        // nothing comes back for acks 0, messages_sent already counted it
+        DeliveryOutcome::Written => {}
+        _ => {
+            topic_metrics.messages_failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    // only an answer from the broker has an ack latency
+    if let (DeliveryOutcome::Acked | DeliveryOutcome::Rejected(_), Some(latency)) = (report.outcome, report.latency) {
+        topic_metrics.ack_latency.observe(latency);
+    }
+
+    settle_spilled(&report.unique_key);
+    release_slot(&report.unique_key);
+    first_written_at.remove(&report.unique_key);
+
+    for interceptor in interceptors {
+        interceptor.on_acknowledgement(report);
+    }
+
+    let result = match report.outcome {
+        DeliveryOutcome::Acked | DeliveryOutcome::Written => Ok(()),
+        DeliveryOutcome::Rejected(err) if err.is_fenced() => Err(ProducerError::Fenced),
+        DeliveryOutcome::Rejected(err) => Err(ProducerError::Broker(err, report.error_msg.to_string())),
+        DeliveryOutcome::Dropped => Err(ProducerError::QueueFull),
+        DeliveryOutcome::Failed => Err(ProducerError::NotConnected),
+    };
+    message_delivered(&report.unique_key, result);
 }
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+use tokio::time::sleep;
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{Producer, rate_limits};
+use crate::brahmaputra::byte_buffers::concrete_functions::rate_limiter::{ProducerRateLimits, RateLimit};
+
+impl Producer {
+    // installs the configured limits, producers without any skip the buckets entirely
+    pub(super) async fn install_rate_limits(&self) {
+        let producer_limit = RateLimit {
+            messages_per_sec: self.max_messages_per_sec,
+            bytes_per_sec: self.max_bytes_per_sec,
+        };
+        let limits = ProducerRateLimits::new(producer_limit, &self.topic_rate_limits.clone().unwrap_or_default());
+
+        *rate_limits.write().await = if limits.is_unlimited() {
+            None
+        } else {
+            Some(Arc::new(limits))
+        };
+    }
+
+    pub(super) fn validate_rate_limits(&self) -> Result<(), ProducerError> {
+        if self.max_messages_per_sec == Some(0) || self.max_bytes_per_sec == Some(0) {
+            return Err(ProducerError::InvalidConfig("rate limits must be greater than 0, leave them unset for no limit".to_string()));
+        }
+
+        for (topic, limit) in self.topic_rate_limits.iter().flatten() {
+            if limit.messages_per_sec == Some(0) || limit.bytes_per_sec == Some(0) {
+                return Err(ProducerError::InvalidConfig(format!("rate limits for topic {} must be greater than 0", topic)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// holds the message back until the producer and topic limits let it into the queue
+pub(super) async fn wait_for_rate_limits(limits: Option<&ProducerRateLimits>, topic: &str, bytes: usize) {
+    if let Some(limits) = limits {
+        let wait = limits.reserve(topic, bytes as u64);
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
+
+// never waits, false when the message would go over a limit
+pub(super) fn try_rate_limits(limits: Option<&ProducerRateLimits>, topic: &str, bytes: usize) -> bool {
+    match limits {
+        Some(limits) => limits.try_reserve(topic, bytes as u64),
+        None => true,
+    }
+}
+
+// a message that reserved its rate but was not queued hands it back
+pub(super) fn release_rate_limits(limits: Option<&ProducerRateLimits>, topic: &str, bytes: usize) {
+    if let Some(limits) = limits {
+        limits.release(topic, bytes as u64);
+    }
+}
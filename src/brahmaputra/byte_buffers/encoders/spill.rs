@@ -0,0 +1,302 @@
+use std::fs::File;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::Semaphore;
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+use tracing::{debug, info, info_span, warn, Instrument};
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{buffer_budget, ChannelWriter, ConnectionSettings, EncodedFrame, in_flight_messages, InFlightMessage, pool_socket_writer, Producer, producer_tasks, QueuedMessage, rate_limits, spill_journal, spill_notify, spill_sync_notify, spill_synced};
+use crate::brahmaputra::byte_buffers::concrete_functions::spill_journal::SpillJournal;
+use crate::brahmaputra::byte_buffers::decoders::producers::producer_decode_frame_route;
+use crate::brahmaputra::byte_buffers::encoders::rate_limits::wait_for_rate_limits;
+
+impl Producer {
+    // opens the journal when spill_dir is set, frames an earlier run left on disk count as queued again
+    pub(super) fn open_spill_journal(&self) -> Result<(), ProducerError> {
+        let journal = match &self.spill_dir {
+            Some(dir) => Some(SpillJournal::open(dir, self.spill_segment_bytes(), self.spill_max_bytes())?),
+            None => None,
+        };
+
+        if let Some(journal) = &journal {
+            for _ in 0..journal.unreplayed() {
+                self.message_queued();
+            }
+            if journal.unreplayed() > 0 {
+                info!(frames = journal.unreplayed(), bytes = journal.total_bytes(), "found frames spilled by an earlier run");
+            }
+        }
+
+        *lock_journal() = journal;
+        spill_synced.send_replace(0);
+
+        Ok(())
+    }
+
+    pub(super) fn spill_segment_bytes(&self) -> u64 {
+        self.spill_segment_bytes.unwrap_or(67108864)
+    }
+
+    pub(super) fn spill_max_bytes(&self) -> u64 {
+        self.spill_max_bytes.unwrap_or(1073741824)
+    }
+
+    // the message goes to disk when no broker is reachable, memory or the queue is full, or earlier messages are still on disk
+    // it comes back when there is no journal or the journal is full, and then takes the usual backpressure path
+    // only messages that are being pushed are spilled, frames already queued when the last connection drops stay in memory
+    // until a connection is back, so they are lost if the process exits before that
+    // the frame is in the page cache when this returns, wait_spill_synced waits for spawn_spill_sync to put it on disk
+    pub(super) fn spill(&self, message: QueuedMessage, budget: &Semaphore, sender: &Sender<QueuedMessage>) -> Option<QueuedMessage> {
+        // without a spill_dir there is no journal, concurrent pushes skip its mutex
+        if self.spill_dir.is_none() {
+            return Some(message);
+        }
+
+        let mut guard = lock_journal();
+        let journal = match guard.as_mut() {
+            Some(journal) => journal,
+            None => {
+                return Some(message);
+            }
+        };
+
+        // once frames are on disk every later message follows them there, so the replay keeps the order
+        let memory_full = budget.available_permits() < message.frame.len() || sender.capacity() == 0;
+        if journal.unreplayed() == 0 && !memory_full && !pool_socket_writer.is_empty() {
+            return Some(message);
+        }
+
+        // a record is written in one piece, so header and payload are put together here
+        match journal.append(&message.unique_key, &message.topic, &message.frame.to_vec()) {
+            Ok(true) => {}
+            Ok(false) => {
+                debug!(max_bytes = self.spill_max_bytes(), "spill journal is full");
+                return Some(message);
+            }
+            Err(err) => {
+                warn!(error = %err, "failed to write frame to the spill journal");
+                return Some(message);
+            }
+        }
+        drop(guard);
+        spill_sync_notify.notify_one();
+
+        // the copy on disk replaces the one kept for retries, the replay keeps it again once it is read back
+        in_flight_messages.remove(&message.unique_key);
+        spill_notify.notify_one();
+
+        None
+    }
+
+    // moves spilled frames back into the queue in the order they were written, whenever a broker is reachable
+    pub(super) fn spawn_spill_replay(&self, settings: Arc<ConnectionSettings>) {
+        if lock_journal().is_none() {
+            return;
+        }
+
+        let acks_zero = self.acks() == "0";
+        let tracks_in_flight = self.tracks_in_flight();
+
+        let task = tokio::spawn(async move {
+            let sender = ChannelWriter.read().await.as_ref().cloned();
+            let budget = buffer_budget.read().await.as_ref().cloned();
+            let (sender, budget) = match (sender, budget) {
+                (Some(sender), Some(budget)) => (sender, budget),
+                _ => {
+                    return;
+                }
+            };
+
+            let mut replaying = false;
+            loop {
+                if lock_journal().as_ref().map(|journal| journal.unreplayed()).unwrap_or(0) == 0 {
+                    if replaying {
+                        info!("spilled frames are back in the queue");
+                        replaying = false;
+                    }
+                    spill_notify.notified().await;
+                    continue;
+                }
+
+                // spilled frames stay on disk until they can actually be written
+                if pool_socket_writer.is_empty() {
+                    sleep(Duration::from_millis(settings.reconnect_backoff_ms)).await;
+                    continue;
+                }
+
+                let next_frame = lock_journal().as_mut().map(|journal| journal.next_frame());
+                let spilled = match next_frame {
+                    Some(Ok(Some(spilled))) => spilled,
+                    // the count and the segments disagree, waiting for the next spill instead of spinning
+                    Some(Ok(None)) => {
+                        spill_notify.notified().await;
+                        continue;
+                    }
+                    Some(Err(err)) => {
+                        warn!(error = %err, "failed to read frame from the spill journal");
+                        sleep(Duration::from_millis(settings.reconnect_backoff_max_ms)).await;
+                        continue;
+                    }
+                    None => {
+                        return;
+                    }
+                };
+
+                if !replaying {
+                    info!("replaying spilled frames");
+                    replaying = true;
+                }
+
+                // replayed frames enter the queue here, so they count against the rate limits here
+                let limits = rate_limits.read().await.as_ref().cloned();
+                wait_for_rate_limits(limits.as_deref(), &spilled.topic, spilled.frame.len()).await;
+
+                // frames larger than the whole budget never made it into the journal
+                let bytes = u32::try_from(spilled.frame.len()).unwrap_or(u32::MAX);
+                let permit = match budget.clone().acquire_many_owned(bytes).await {
+                    Ok(permit) => permit,
+                    Err(_) => {
+                        return;
+                    }
+                };
+
+                // the journal only keeps the frame, the partition it was encoded for picks the connection again
+                let (partition, key) = producer_decode_frame_route(spilled.frame.clone());
+                let frame = EncodedFrame::from(spilled.frame);
+                let mut message = QueuedMessage {
+                    unique_key: spilled.unique_key,
+                    topic: spilled.topic,
+                    partition,
+                    key,
+                    frame,
+                    permit: None,
+                };
+
+                // kept for a retry again, from here on the frame is handled like one that never left memory
+                if tracks_in_flight {
+                    in_flight_messages.insert(message.unique_key.to_string(), InFlightMessage {
+                        topic: message.topic.to_string(),
+                        partition: message.partition,
+                        key: message.key.to_string(),
+                        sequence: -1,
+                        frame: message.frame.clone(),
+                        attempts: 0,
+                        permit: Some(permit),
+                        created_at: Instant::now(),
+                    });
+                } else {
+                    message.permit = Some(permit);
+                }
+
+                let unique_key = message.unique_key.to_string();
+                if sender.send(message).await.is_err() {
+                    return;
+                }
+
+                // only now can new messages skip the journal without overtaking this one
+                if let Some(journal) = lock_journal().as_mut() {
+                    journal.replayed();
+                }
+
+                // nothing comes back for acks 0, the frame is done once it is queued
+                if acks_zero {
+                    settle_spilled(&unique_key);
+                }
+            }
+        }.instrument(info_span!("spill_replay")));
+
+        if let Some(previous) = producer_tasks.insert("spill_replay".to_string(), task) {
+            previous.abort();
+        }
+    }
+}
+
+impl Producer {
+    // syncs the journal off the runtime, frames appended while a sync runs all go to disk with the next one
+    pub(super) fn spawn_spill_sync(&self) {
+        if lock_journal().is_none() {
+            return;
+        }
+
+        let task = tokio::spawn(async move {
+            loop {
+                spill_sync_notify.notified().await;
+
+                let (appended, files) = match lock_journal().as_mut() {
+                    Some(journal) => journal.take_unsynced(),
+                    None => {
+                        return;
+                    }
+                };
+                if !sync_files(files).await {
+                    return;
+                }
+                spill_synced.send_replace(appended);
+            }
+        }.instrument(info_span!("spill_sync")));
+
+        if let Some(previous) = producer_tasks.insert("spill_sync".to_string(), task) {
+            previous.abort();
+        }
+    }
+}
+
+// waits until every frame spilled so far is on disk, returns right away without a journal
+pub(super) async fn wait_spill_synced() {
+    let target = match lock_journal().as_ref() {
+        Some(journal) => journal.appended(),
+        None => {
+            return;
+        }
+    };
+
+    let mut synced = spill_synced.subscribe();
+    while *synced.borrow_and_update() < target {
+        if synced.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+// false when the runtime is shutting down, a failed sync leaves the frames in the page cache and only warns
+// since they are still replayed from there
+async fn sync_files(files: Vec<File>) -> bool {
+    if files.is_empty() {
+        return true;
+    }
+
+    match spawn_blocking(move || files.iter().try_for_each(|file| file.sync_data())).await {
+        Ok(Ok(())) => true,
+        Ok(Err(err)) => {
+            warn!(error = %err, "failed to sync the spill journal, spilled frames are not safe from a crash");
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+// the frame got its final ack, its segment is deleted once every frame in it did
+pub(super) fn settle_spilled(unique_key: &str) {
+    if let Some(journal) = lock_journal().as_mut() {
+        if let Err(err) = journal.settle(unique_key) {
+            warn!(error = %err, "failed to delete acked spill segment");
+        }
+    }
+}
+
+// frames spilled since the last sync go to disk before the journal is closed, pushes still waiting for them return
+pub(super) async fn close_spill_journal() {
+    let journal = lock_journal().take();
+    if let Some(mut journal) = journal {
+        let (_, files) = journal.take_unsynced();
+        sync_files(files).await;
+    }
+    spill_synced.send_replace(u64::MAX);
+}
+
+// a task that panicked while holding the lock leaves the journal usable
+fn lock_journal() -> std::sync::MutexGuard<'static, Option<SpillJournal>> {
+    spill_journal.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use bytes::Bytes;
+use tokio::sync::oneshot;
+use tokio::time::{timeout, Instant};
+use tracing::field::Empty;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::brahmaputra::byte_buffers::concrete_functions::byte_buffer::ByteBuff;
+use crate::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::enums::{MessageCode, TransactionState};
+use crate::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{pending_requests, Producer, producer_identity, ProducerRecord, transaction_partitions, transaction_state};
+use crate::brahmaputra::byte_buffers::concrete_functions::select_partition::select_partition;
+use crate::brahmaputra::byte_buffers::encoders::producers::{enqueue_frame, wait_until_settled};
+
+impl Producer {
+    pub async fn begin_transaction(&mut self) -> Result<(), ProducerError> {
+        self.check_transaction_state(TransactionState::Ready).await?;
+
+        transaction_partitions.clear();
+        *transaction_state.write().await = TransactionState::InTransaction;
+
+        Ok(())
+    }
+
+    pub async fn send(&mut self, topic: String, key: String, msg: Vec<u8>) -> Result<(), ProducerError> {
+        self.send_record(ProducerRecord::new(topic, key, Bytes::from(msg))).await
+    }
+
+    #[instrument(name = "send", skip_all, fields(topic = %record.topic, partition = Empty, unique_key = Empty))]
+    pub(super) async fn send_record(&mut self, record: ProducerRecord) -> Result<(), ProducerError> {
+        self.check_transaction_state(TransactionState::InTransaction).await?;
+
+        // interceptors can change the key, so the partition is picked after they ran
+        let record = self.intercept(record);
+
+        // the broker has to know about every partition before it sees transactional frames for it
+        let partition = select_partition(record.key.to_string(), 5);
+        if !transaction_partitions.contains_key(&(record.topic.to_string(), partition)) {
+            self.add_partition_to_transaction(record.topic.to_string(), partition).await?;
+            transaction_partitions.insert((record.topic.to_string(), partition), ());
+        }
+
+        let identity = *producer_identity.read().await;
+        let message = self.producer_encode_msg_v2(record, partition, identity)?;
+
+        self.enqueue_message(message).await
+    }
+
+    // commits consumer offsets as part of the transaction, so they only move when the output is committed
+    pub async fn send_offsets_to_transaction(&mut self, offsets: HashMap<(String, i32), i64>, group_id: String) -> Result<(), ProducerError> {
+        self.check_transaction_state(TransactionState::InTransaction).await?;
+
+        let unique_key = Uuid::new_v4().to_string();
+        let mut bb = self.producer_encode_txn_header(MessageCode::TxnOffsetCommit, unique_key.to_string()).await?;
+
+        // consumer group
+        bb.put_string(group_id);
+
+        // offsets for every topic partition
+        bb.put_int(offsets.len() as i32);
+        for ((topic, partition), offset) in offsets {
+            bb.put_string(topic);
+            bb.put_int(partition);
+            bb.put_long(offset);
+        }
+
+        self.send_control_request(bb, unique_key).await
+    }
+
+    pub async fn commit_transaction(&mut self) -> Result<(), ProducerError> {
+        if *transaction_state.read().await == TransactionState::AbortableError {
+            return Err(ProducerError::TransactionFailed);
+        }
+        self.check_transaction_state(TransactionState::InTransaction).await?;
+        *transaction_state.write().await = TransactionState::Committing;
+
+        match self.end_transaction(true).await {
+            Ok(_) => {
+                transaction_partitions.clear();
+                *transaction_state.write().await = TransactionState::Ready;
+                Ok(())
+            }
+            Err(err) => {
+                // leaving the transaction open so the caller can abort it, unless this instance was fenced or a message failed
+                let mut state = transaction_state.write().await;
+                if *state == TransactionState::Committing {
+                    *state = TransactionState::InTransaction;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    // also ends a transaction one of its messages failed in
+    pub async fn abort_transaction(&mut self) -> Result<(), ProducerError> {
+        let previous = *transaction_state.read().await;
+        if previous != TransactionState::AbortableError {
+            self.check_transaction_state(TransactionState::InTransaction).await?;
+        }
+        *transaction_state.write().await = TransactionState::Aborting;
+
+        match self.end_transaction(false).await {
+            Ok(_) => {
+                transaction_partitions.clear();
+                *transaction_state.write().await = TransactionState::Ready;
+                Ok(())
+            }
+            Err(err) => {
+                let mut state = transaction_state.write().await;
+                if *state == TransactionState::Aborting {
+                    *state = previous;
+                }
+                Err(err)
+            }
+        }
+    }
+
+    async fn check_transaction_state(&self, expected: TransactionState) -> Result<(), ProducerError> {
+        if self.transactional_id.is_none() {
+            return Err(ProducerError::InvalidConfig("transactions require a transactional id".to_string()));
+        }
+
+        let state = *transaction_state.read().await;
+        match state {
+            _ if state == expected => Ok(()),
+            TransactionState::Uninitialized => Err(ProducerError::NotConnected),
+            TransactionState::Fenced => Err(ProducerError::Fenced),
+            _ => Err(ProducerError::InvalidTransactionState(format!("expected {:?} but was {:?}", expected, state))),
+        }
+    }
+
+    async fn add_partition_to_transaction(&self, topic: String, partition: u32) -> Result<(), ProducerError> {
+        let unique_key = Uuid::new_v4().to_string();
+        let mut bb = self.producer_encode_txn_header(MessageCode::AddPartitionsToTxn, unique_key.to_string()).await?;
+
+        // topic
+        bb.put_string(topic);
+
+        // partition
+        bb.put_int(partition as i32);
+
+        self.send_control_request(bb, unique_key).await
+    }
+
+    async fn end_transaction(&self, commit: bool) -> Result<(), ProducerError> {
+        let transaction_timeout = Duration::from_millis(self.transaction_timeout_ms.unwrap_or(60000));
+
+        // every frame of the transaction has to be acked before the marker is written
+        if !wait_until_settled(Instant::now() + transaction_timeout).await {
+            return Err(ProducerError::Timeout("waiting for transactional messages to be acked".to_string()));
+        }
+
+        // a message that failed while the acks were awaited means the commit cannot go ahead
+        if commit && *transaction_state.read().await == TransactionState::AbortableError {
+            return Err(ProducerError::TransactionFailed);
+        }
+
+        let unique_key = Uuid::new_v4().to_string();
+        let mut bb = self.producer_encode_txn_header(MessageCode::EndTxn, unique_key.to_string()).await?;
+
+        // transaction marker, true commits and false aborts
+        bb.put_bool(commit);
+
+        self.send_control_request(bb, unique_key).await
+    }
+
+    async fn producer_encode_txn_header(&self, message_code: MessageCode, unique_key: String) -> Result<ByteBuff, ProducerError> {
+        let identity = match *producer_identity.read().await {
+            Some(identity) => identity,
+            None => {
+                return Err(ProducerError::NotConnected);
+            }
+        };
+
+        let mut bb = ByteBuff {
+            multiplier: 10000.0,
+            endian: "big".to_string(),
+            ..Default::default()
+        };
+
+        // into big endian format
+        bb.init("big".to_string());
+
+        // version number
+        bb.put_string("V_1".to_string());
+
+        // message type either producer or consumer
+        bb.put_string("P".to_string());
+
+        // message code for the transaction request
+        bb.put_int(message_code as i32);
+
+        // put unique key, the broker echoes it back in the response
+        bb.put_string(unique_key);
+
+        // transactional id
+        bb.put_string(self.transactional_id.clone().unwrap_or_default());
+
+        // producer id and epoch
+        bb.put_long(identity.producer_id);
+        bb.put_short(identity.producer_epoch);
+
+        Ok(bb)
+    }
+
+    async fn send_control_request(&self, bb: ByteBuff, unique_key: String) -> Result<(), ProducerError> {
+
+        // wrapping the message into another byte array to get its total length
+        let mut wrap_byte = ByteBuff {
+            multiplier: 10000.0,
+            endian: "big".to_string(),
+            ..Default::default()
+        };
+
+        wrap_byte.put(bb.to_array());
+
+        let (responder, response) = oneshot::channel();
+        pending_requests.insert(unique_key.to_string(), responder);
+
+        if let Err(err) = enqueue_frame(wrap_byte.to_array().into(), None).await {
+            pending_requests.remove(&unique_key);
+            return Err(err);
+        }
+
+        let transaction_timeout = Duration::from_millis(self.transaction_timeout_ms.unwrap_or(60000));
+        let (error_code, error_msg) = match timeout(transaction_timeout, response).await {
+            Ok(Ok(response)) => response,
+            // the producer was closed before the broker responded
+            Ok(Err(_)) => {
+                return Err(ProducerError::NotConnected);
+            }
+            Err(_) => {
+                pending_requests.remove(&unique_key);
+                return Err(ProducerError::Timeout("waiting for the broker to respond".to_string()));
+            }
+        };
+
+        match BrokerError::from_code(error_code) {
+            None => Ok(()),
+            Some(err) if err.is_fenced() => {
+                *transaction_state.write().await = TransactionState::Fenced;
+                Err(ProducerError::Fenced)
+            }
+            Some(err) => Err(ProducerError::Broker(err, format!("broker rejected transaction request: {}", error_msg))),
+        }
+    }
+}
+
+// a message of the open transaction failed for good, from here on it can only be aborted
+pub(super) async fn fail_transaction() {
+    let mut state = transaction_state.write().await;
+    if *state == TransactionState::InTransaction || *state == TransactionState::Committing {
+        *state = TransactionState::AbortableError;
+    }
+}
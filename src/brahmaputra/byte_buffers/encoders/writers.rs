@@ -0,0 +1,417 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Notify;
+use tokio::time::{sleep_until, Instant};
+use tracing::{debug_span, info_span, warn, Instrument};
+use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{connection_windows, ConnectionSettings, ControlFrame, DeliveryOutcome, EncodedFrame, FrameKind, first_written_at, in_flight_slots, InFlightSlot, OutgoingFrame, pool_socket_writer, producer_metrics, producer_tasks, queued_frames, writer_queues, WriterQueue};
+use crate::brahmaputra::byte_buffers::encoders::connections::{mark_dead, throttled_until, write_vectored_all};
+use crate::brahmaputra::byte_buffers::encoders::producers::{frame_written, message_finished};
+
+// writev takes at most 1024 slices on linux and every frame is a header and a payload
+pub(super) const MAX_BATCH_FRAMES: usize = 512;
+
+// the writer outlives reconnects of its connection and is stopped when the producer closes
+pub(super) fn spawn_writer(conn_number: i32, control_tx: UnboundedSender<ControlFrame>, settings: Arc<ConnectionSettings>, takes_slots: bool) -> Arc<WriterQueue> {
+    let queue = Arc::new(WriterQueue {
+        frames: Mutex::new(VecDeque::new()),
+        capacity: settings.writer_queue_size,
+        waker: Notify::new(),
+        room: Notify::new(),
+    });
+    writer_queues.insert(conn_number, Arc::clone(&queue));
+
+    let writer = tokio::spawn(
+        write_connection(conn_number, Arc::clone(&queue), control_tx, settings, takes_slots)
+            .instrument(info_span!("writer", connection = conn_number))
+    );
+
+    if let Some(previous) = producer_tasks.insert(format!("writer-{}", conn_number), writer) {
+        previous.abort();
+    }
+
+    queue
+}
+
+// a message that finds the queue full is handed back, the dispatcher waits for room so backpressure reaches push
+// retries and control frames always go in, they are what frees the slots the queued messages wait for
+pub(super) fn try_queue(queue: &WriterQueue, outgoing: OutgoingFrame) -> Option<OutgoingFrame> {
+    let mut frames = queue.frames.lock().unwrap();
+    if outgoing.kind == FrameKind::Message && frames.len() >= queue.capacity {
+        return Some(outgoing);
+    }
+
+    keep(&mut frames, outgoing);
+    drop(frames);
+
+    queue.waker.notify_one();
+    None
+}
+
+// a control frame for this connection only, a heartbeat judges the connection it was sent on, false without a writer for it
+pub(super) fn queue_control(conn_number: i32, frame: EncodedFrame) -> bool {
+    let queue = match writer_queues.get(&conn_number) {
+        Some(queue) => Arc::clone(queue.value()),
+        None => {
+            return false;
+        }
+    };
+
+    queued_frames.fetch_add(1, Ordering::SeqCst);
+    let _ = try_queue(&queue, OutgoingFrame {
+        topic: String::new(),
+        partition: 0,
+        key: String::new(),
+        unique_key: String::new(),
+        frame,
+        permit: None,
+        kind: FrameKind::Control,
+        queued_at: std::time::Instant::now(),
+    });
+
+    true
+}
+
+// writes what may go out now in one batch and flushes once nothing is left to write
+// frames that have to wait for a slot, a throttle or a reconnect stay queued in order, with their buffer memory
+async fn write_connection(conn_number: i32, queue: Arc<WriterQueue>, control_tx: UnboundedSender<ControlFrame>, settings: Arc<ConnectionSettings>, takes_slots: bool) {
+    let mut unflushed = false;
+
+    loop {
+        if write_batch(conn_number, &queue, &control_tx, &settings, takes_slots).await > 0 {
+            unflushed = true;
+            continue;
+        }
+
+        // frames may have arrived during the flush, so the queue is looked at again before waiting
+        if unflushed {
+            flush(conn_number, &settings).await;
+            unflushed = false;
+            continue;
+        }
+
+        // frames waiting out a throttle go once it is over, nothing else would wake the writer for them
+        let throttle_ends = if queue.frames.lock().unwrap().is_empty() { None } else { throttled_until(conn_number) };
+
+        // a wake up that came while writing is kept by the notify, so none is missed
+        tokio::select! {
+            biased;
+            _ = queue.waker.notified() => {}
+            _ = sleep_until(Instant::from_std(throttle_ends.unwrap_or_else(std::time::Instant::now))), if throttle_ends.is_some() => {}
+        }
+    }
+}
+
+// a retry was queued before the messages waiting behind it, but after earlier retries
+fn keep(waiting: &mut VecDeque<OutgoingFrame>, outgoing: OutgoingFrame) {
+    if outgoing.kind == FrameKind::Retry {
+        let position = waiting.iter().take_while(|waiting| waiting.kind == FrameKind::Retry).count();
+        waiting.insert(position, outgoing);
+    } else {
+        waiting.push_back(outgoing);
+    }
+}
+
+// takes the frames at the front that may go out now and writes them with one vectored write, returns how many were written
+async fn write_batch(conn_number: i32, queue: &WriterQueue, control_tx: &UnboundedSender<ControlFrame>, settings: &Arc<ConnectionSettings>, takes_slots: bool) -> usize {
+    // messages wait for a reconnecting connection instead of moving, another connection could overtake them
+    let socket = match pool_socket_writer.get(&conn_number) {
+        Some(socket) => socket.value().clone(),
+        None => {
+            reroute_control(queue, control_tx);
+            return 0;
+        }
+    };
+
+    if throttled_until(conn_number).is_some() {
+        return 0;
+    }
+
+    // messages and retries hold a slot in the window, control frames never wait for one
+    let batch: Vec<OutgoingFrame> = {
+        let mut frames = queue.frames.lock().unwrap();
+        let mut count = 0;
+        for outgoing in frames.iter().take(MAX_BATCH_FRAMES) {
+            if takes_slots && outgoing.kind != FrameKind::Control && !take_slot(conn_number, outgoing) {
+                break;
+            }
+            count += 1;
+        }
+        frames.drain(..count).collect()
+    };
+
+    if batch.is_empty() {
+        return 0;
+    }
+    queue.room.notify_one();
+
+    // stamped before the write, an ack can come back before the write returns
+    let now = std::time::Instant::now();
+    for outgoing in batch.iter().filter(|outgoing| outgoing.kind != FrameKind::Control) {
+        first_written_at.entry(outgoing.unique_key.to_string()).or_insert(now);
+    }
+
+    let count = batch.len();
+    let bytes: usize = batch.iter().map(|outgoing| outgoing.frame.len()).sum();
+    let parts: Vec<&[u8]> = batch.iter()
+        .flat_map(|outgoing| [&outgoing.frame.header[..], &outgoing.frame.payload[..]])
+        .collect();
+
+    let mut guard = socket.write().await;
+    let written = match guard.as_mut() {
+        Some(sock) => write_vectored_all(sock, &parts).instrument(debug_span!("write", frames = count, bytes)).await,
+        None => Err((0, Error::new(ErrorKind::NotConnected, "connection is closed"))),
+    };
+    drop(guard);
+    drop(parts);
+
+    let written_bytes = match &written {
+        Ok(_) => bytes,
+        Err((written_bytes, _)) => *written_bytes,
+    };
+    let (batch, unwritten) = split_written(batch, written_bytes);
+    let done = batch.len();
+
+    let connection_metrics = producer_metrics.connection(conn_number);
+    for outgoing in batch {
+        if outgoing.kind == FrameKind::Message {
+            let topic_metrics = producer_metrics.topic(&outgoing.topic);
+            topic_metrics.messages_sent.fetch_add(1, Ordering::Relaxed);
+            topic_metrics.bytes_sent.fetch_add(outgoing.frame.len() as u64, Ordering::Relaxed);
+        }
+
+        connection_metrics.frames_written.fetch_add(1, Ordering::Relaxed);
+        connection_metrics.bytes_written.fetch_add(outgoing.frame.len() as u64, Ordering::Relaxed);
+
+        // with acks 0 the write is all that ever happens to a message
+        if !takes_slots && outgoing.kind == FrameKind::Message {
+            message_finished(&outgoing.report(DeliveryOutcome::Written, String::new()), &settings.interceptors);
+        }
+        frame_written();
+    }
+
+    if let Err((_, err)) = written {
+        warn!(error = %err, frames = count - done, "failed to write frames, they wait for the connection to come back");
+        connection_metrics.write_errors.fetch_add(1, Ordering::Relaxed);
+
+        requeue(queue, unwritten, takes_slots);
+        mark_dead(conn_number, Arc::clone(settings));
+        reroute_control(queue, control_tx);
+    }
+
+    done
+}
+
+// a frame counts as written once all of it went out, returns the written frames and the rest in batch order
+fn split_written(mut batch: Vec<OutgoingFrame>, mut written_bytes: usize) -> (Vec<OutgoingFrame>, Vec<OutgoingFrame>) {
+    let mut done = 0;
+    while done < batch.len() && batch[done].frame.len() <= written_bytes {
+        written_bytes -= batch[done].frame.len();
+        done += 1;
+    }
+
+    let unwritten = batch.split_off(done);
+    (batch, unwritten)
+}
+
+// back at the front in the order they were taken, unless the reader saw the connection die first
+// and already sent the frame again or settled it along with its slot
+fn requeue(queue: &WriterQueue, unwritten: Vec<OutgoingFrame>, takes_slots: bool) {
+    let mut frames = queue.frames.lock().unwrap();
+    for outgoing in unwritten.into_iter().rev() {
+        if takes_slots && outgoing.kind != FrameKind::Control && in_flight_slots.remove(&outgoing.unique_key).is_none() {
+            frame_written();
+            continue;
+        }
+        frames.push_front(outgoing);
+    }
+}
+
+// the batch went out without a flush, this runs once the writer has nothing left to write
+async fn flush(conn_number: i32, settings: &Arc<ConnectionSettings>) {
+    let socket = match pool_socket_writer.get(&conn_number) {
+        Some(socket) => socket.value().clone(),
+        None => {
+            return;
+        }
+    };
+
+    let mut guard = socket.write().await;
+    let flushed = match guard.as_mut() {
+        Some(sock) => sock.flush().await,
+        None => Ok(()),
+    };
+    drop(guard);
+
+    if let Err(err) = flushed {
+        warn!(error = %err, "failed to flush connection");
+        producer_metrics.connection(conn_number).write_errors.fetch_add(1, Ordering::Relaxed);
+        mark_dead(conn_number, Arc::clone(settings));
+    }
+}
+
+// control frames go back to the dispatcher, which hands them to the next live connection
+fn reroute_control(queue: &WriterQueue, control_tx: &UnboundedSender<ControlFrame>) {
+    let control: VecDeque<OutgoingFrame> = {
+        let mut frames = queue.frames.lock().unwrap();
+        if frames.iter().all(|outgoing| outgoing.kind != FrameKind::Control) {
+            return;
+        }
+
+        let (control, pinned): (VecDeque<OutgoingFrame>, VecDeque<OutgoingFrame>) = frames.drain(..).partition(|outgoing| outgoing.kind == FrameKind::Control);
+        *frames = pinned;
+        control
+    };
+    queue.room.notify_one();
+
+    for outgoing in control {
+        // still counted as queued, so it has to be counted out when nobody will write it
+        if control_tx.send(ControlFrame { frame: outgoing.frame, retry: None }).is_err() {
+            frame_written();
+        }
+    }
+}
+
+// a frame holds a slot in its connection's window from the write until its final outcome, a retry keeps the one it has
+fn take_slot(conn_number: i32, outgoing: &OutgoingFrame) -> bool {
+    if in_flight_slots.get(&outgoing.unique_key).map(|slot| slot.conn_number == conn_number).unwrap_or(false) {
+        return true;
+    }
+
+    let window = match connection_windows.get(&conn_number) {
+        Some(window) => Arc::clone(window.value()),
+        None => {
+            return false;
+        }
+    };
+
+    // taken before the write, an ack can come back before the write returns
+    match window.try_acquire_owned() {
+        Ok(permit) => {
+            in_flight_slots.insert(outgoing.unique_key.to_string(), InFlightSlot {
+                conn_number,
+                permit,
+                topic: outgoing.topic.to_string(),
+                partition: outgoing.partition,
+                key: outgoing.key.to_string(),
+            });
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use bytes::Bytes;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::{mpsc, Notify, RwLock};
+    use crate::brahmaputra::byte_buffers::concrete_functions::producer_stream::ProducerStream;
+    use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::{ConnectionSettings, EncodedFrame, FrameKind, OutgoingFrame, pool_socket_writer, RetryPolicy, WriterQueue};
+    use super::{requeue, split_written, write_batch, MAX_BATCH_FRAMES};
+
+    // connection numbers no producer uses, the writer state is global
+    const WRITTEN_CONNECTION: i32 = 9001;
+    const FAILED_CONNECTION: i32 = 9002;
+
+    fn settings() -> Arc<ConnectionSettings> {
+        Arc::new(ConnectionSettings {
+            servers: "127.0.0.1:1".to_string(),
+            pool_size: 1,
+            reconnect_backoff_ms: 3600000,
+            reconnect_backoff_max_ms: 3600000,
+            heartbeat_interval_ms: 0,
+            heartbeat_timeout_ms: 0,
+            retry_policy: RetryPolicy { retries: 0, retry_backoff_ms: 0, retry_backoff_max_ms: 0 },
+            max_in_flight_per_connection: 1,
+            writer_queue_size: 1000,
+            tls: None,
+            authenticator: None,
+            interceptors: Vec::new(),
+        })
+    }
+
+    // every frame is 8 bytes, its number in the header and the payload
+    fn frame(i: u32) -> OutgoingFrame {
+        OutgoingFrame {
+            topic: "writer_test".to_string(),
+            partition: 0,
+            key: String::new(),
+            unique_key: format!("writer-{}", i),
+            frame: EncodedFrame { header: Bytes::copy_from_slice(&i.to_be_bytes()), payload: Bytes::copy_from_slice(&i.to_be_bytes()) },
+            permit: None,
+            kind: FrameKind::Message,
+            queued_at: std::time::Instant::now(),
+        }
+    }
+
+    fn queue_of(frames: impl Iterator<Item = u32>) -> WriterQueue {
+        WriterQueue {
+            frames: Mutex::new(frames.map(frame).collect()),
+            capacity: 1000,
+            waker: Notify::new(),
+            room: Notify::new(),
+        }
+    }
+
+    fn numbers(frames: &VecDeque<OutgoingFrame>) -> Vec<u32> {
+        frames.iter().map(|outgoing| u32::from_be_bytes(outgoing.frame.header[..].try_into().unwrap())).collect()
+    }
+
+    #[tokio::test]
+    async fn queued_frames_go_out_in_batches_of_at_most_max_batch_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+        let (_, writer) = tokio::io::split(ProducerStream::Plain(client));
+        pool_socket_writer.insert(WRITTEN_CONNECTION, Arc::new(RwLock::new(Some(writer))));
+
+        let queue = queue_of(0..600);
+        let (control_tx, _control_rx) = mpsc::unbounded_channel();
+        let settings = settings();
+
+        // one call takes a full batch and writes it at once, the rest waits for the next
+        assert_eq!(write_batch(WRITTEN_CONNECTION, &queue, &control_tx, &settings, false).await, MAX_BATCH_FRAMES);
+        assert_eq!(numbers(&queue.frames.lock().unwrap()), (512..600).collect::<Vec<u32>>());
+        assert_eq!(write_batch(WRITTEN_CONNECTION, &queue, &control_tx, &settings, false).await, 88);
+        assert!(queue.frames.lock().unwrap().is_empty());
+
+        let mut received = vec![0u8; 600 * 8];
+        peer.read_exact(&mut received).await.unwrap();
+        let received: Vec<u32> = received.chunks(8).map(|chunk| u32::from_be_bytes(chunk[..4].try_into().unwrap())).collect();
+        assert_eq!(received, (0..600).collect::<Vec<u32>>());
+
+        pool_socket_writer.remove(&WRITTEN_CONNECTION);
+    }
+
+    #[tokio::test]
+    async fn a_failed_write_puts_the_whole_batch_back_in_order() {
+        // the connection is known but its socket already went away
+        pool_socket_writer.insert(FAILED_CONNECTION, Arc::new(RwLock::new(None)));
+
+        let queue = queue_of(0..600);
+        let (control_tx, _control_rx) = mpsc::unbounded_channel();
+
+        assert_eq!(write_batch(FAILED_CONNECTION, &queue, &control_tx, &settings(), false).await, 0);
+        assert_eq!(numbers(&queue.frames.lock().unwrap()), (0..600).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn frames_cut_off_by_a_short_write_go_back_in_front_of_later_ones() {
+        let batch: Vec<OutgoingFrame> = (0..4).map(frame).collect();
+
+        // the second frame went out half way
+        let (written, unwritten) = split_written(batch, 12);
+        assert_eq!(written.len(), 1);
+
+        let queue = queue_of(4..6);
+        requeue(&queue, unwritten, false);
+        assert_eq!(numbers(&queue.frames.lock().unwrap()), vec![1, 2, 3, 4, 5]);
+    }
+}
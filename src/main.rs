@@ -1,34 +1,44 @@
-mod brahmaputra;
-use tokio::io::AsyncReadExt;
-use tokio::task;
-use crate::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use std::time::Duration;
+use tracing::error;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_builder::ProducerBuilder;
 
 #[tokio::main]
 async fn main() {
+    // RUST_LOG style filtering is left to the subscriber, info and above by default
+    tracing_subscriber::fmt::init();
 
-    let mut producer = Producer{
-        servers: "localhost:9092".to_string(),
-        acks: Some("all".to_string()),
-        max_buffer_size: Some(1000000000),
-        retries: Some(5),
-        compression_type: Some("lz4".to_string()),
-        pool: Some(100),
-        ..Default::default()
+    // BRAHMAPUTRA_* environment variables override the settings below
+    let builder = ProducerBuilder::new()
+        .servers("localhost:9092")
+        .acks("all")
+        .max_buffer_size(1000000000)
+        .retries(5)
+        .compression_type("lz4")
+        .pool(100)
+        .max_messages_per_sec(100000)
+        .with_env();
+
+    let mut producer = match builder.and_then(|builder| builder.build()) {
+        Ok(producer) => producer,
+        Err(err) => {
+            error!(error = %err, "invalid producer config");
+            return;
+        }
     };
 
-    producer.connect_producer().await;
+    if let Err(err) = producer.connect_producer().await {
+        error!(error = %err, "failed to connect producer");
+        return;
+    }
 
-    for i in 0..100000000{
-        producer.push("loggers".to_string(), "sudeep key".to_string(), "hello sudeep".as_bytes().to_vec()).await;
+    for _ in 0..100000000{
+        if let Err(err) = producer.push("loggers".to_string(), "sudeep key".to_string(), "hello sudeep".as_bytes().to_vec()).await {
+            error!(error = %err, "failed to push message");
+            break;
+        }
     }
 
-    loop{
-        let result = task::spawn_blocking(|| {
-            // Blocking operation here
-            std::thread::sleep(std::time::Duration::from_secs(5));
-            "Blocking operation completed"
-        })
-        .await
-        .unwrap();
+    if let Err(err) = producer.close(Duration::from_secs(30)).await {
+        error!(error = %err, "failed to close producer");
     }
 }
@@ -0,0 +1,31 @@
+mod common;
+
+use std::time::Duration;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::{producer_metrics, Producer};
+use common::{Faults, MockBroker};
+
+// without retries no frame is kept after the write, the latency still has to be measured for every ack
+#[tokio::test]
+async fn ack_latency_counts_frames_that_are_not_kept() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        retries: Some(0),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    for i in 0..5 {
+        producer.push("latency".to_string(), "key".to_string(), i.to_string().into_bytes()).await.unwrap();
+    }
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+
+    let topic_metrics = producer_metrics.topic("latency");
+    assert_eq!(topic_metrics.ack_latency.count(), 5);
+    assert!(producer_metrics.render_prometheus().contains("brahmaputra_producer_ack_latency_seconds_count{topic=\"latency\"} 5"));
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
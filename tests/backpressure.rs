@@ -0,0 +1,49 @@
+mod common;
+
+use std::time::Duration;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+const MESSAGES: u32 = 50;
+
+// the broker holds the first ack, so the messages behind it wait in the writer queue until the buffer memory runs out
+#[tokio::test]
+async fn drop_oldest_evicts_from_the_writer_queue() {
+    let broker = MockBroker::start(Faults::default()).await;
+    broker.pause_acks();
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        max_in_flight_per_connection: Some(1),
+        buffer_memory_bytes: Some(10000),
+        backpressure_policy: Some(BackpressurePolicy::DropOldest),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    for i in 0..MESSAGES {
+        let mut payload = i.to_be_bytes().to_vec();
+        payload.resize(1000, 0);
+        producer.try_push("backpressure".to_string(), "key".to_string(), payload).unwrap();
+
+        // lets the dispatcher hand the message to the writer before the next push
+        tokio::task::yield_now().await;
+    }
+
+    broker.resume_acks();
+    producer.flush(Duration::from_secs(10)).await.unwrap();
+
+    let sent: Vec<u32> = broker.produced().iter()
+        .map(|produced| u32::from_be_bytes(produced.payload[..4].try_into().unwrap()))
+        .collect();
+
+    assert!(producer.dropped_messages() > 0);
+    assert_eq!(sent.len() as u64 + producer.dropped_messages(), MESSAGES as u64);
+    assert!(sent.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(sent.last(), Some(&(MESSAGES - 1)));
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::blocking_producer::BlockingProducer;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::rate_limiter::RateLimit;
+use common::{Faults, MockBroker};
+
+fn producer(servers: &str) -> Producer {
+    Producer {
+        servers: servers.to_string(),
+        pool: Some(1),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    }
+}
+
+// the producer state is global, a second producer of either kind has to be refused until the first one is closed
+// a send waiting out a rate limit must not hold up sends from other threads
+#[tokio::test(flavor = "multi_thread")]
+async fn only_one_producer_is_connected_at_a_time() {
+    let broker = MockBroker::start(Faults::default()).await;
+    let servers = broker.servers.to_string();
+
+    let blocking_servers = servers.to_string();
+    tokio::task::spawn_blocking(move || {
+        let first = BlockingProducer::connect(Producer {
+            topic_rate_limits: Some(HashMap::from([("slow".to_string(), RateLimit { messages_per_sec: Some(1), bytes_per_sec: None })])),
+            ..producer(&blocking_servers)
+        }).unwrap();
+        assert!(matches!(BlockingProducer::connect(producer(&blocking_servers)), Err(ProducerError::InvalidConfig(_))));
+
+        let first = Arc::new(first);
+        first.send("slow".to_string(), "key".to_string(), b"slow".to_vec()).unwrap();
+
+        // the second slow message waits about a second for the rate limit
+        let slow_done = Arc::new(AtomicBool::new(false));
+        let slow = {
+            let (first, slow_done) = (Arc::clone(&first), Arc::clone(&slow_done));
+            thread::spawn(move || {
+                first.send("slow".to_string(), "key".to_string(), b"slow".to_vec()).unwrap();
+                slow_done.store(true, Ordering::SeqCst);
+            })
+        };
+        thread::sleep(Duration::from_millis(100));
+
+        let started = Instant::now();
+        first.send("fast".to_string(), "key".to_string(), b"fast".to_vec()).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500));
+        assert!(!slow_done.load(Ordering::SeqCst));
+
+        slow.join().unwrap();
+        let mut first = Arc::try_unwrap(first).unwrap();
+        first.close(Duration::from_secs(5)).unwrap();
+    }).await.unwrap();
+
+    let mut second = producer(&servers);
+    second.connect_producer().await.unwrap();
+
+    // the async producer holds the state now, so the blocking one is refused in turn
+    let blocking_servers = servers.to_string();
+    tokio::task::spawn_blocking(move || {
+        assert!(matches!(BlockingProducer::connect(producer(&blocking_servers)), Err(ProducerError::InvalidConfig(_))));
+    }).await.unwrap();
+    assert!(matches!(producer(&servers).connect_producer().await, Err(ProducerError::InvalidConfig(_))));
+
+    second.push_and_wait("async".to_string(), "key".to_string(), b"second".to_vec()).await.unwrap();
+    second.close(Duration::from_secs(5)).await.unwrap();
+
+    let topics: Vec<String> = broker.produced().into_iter().map(|produced| produced.topic).collect();
+    assert_eq!(topics, vec!["slow", "fast", "slow", "async"]);
+}
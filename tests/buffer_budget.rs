@@ -0,0 +1,66 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::sleep;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// message too large, the broker will not take the frame however often it is sent
+const MESSAGE_TOO_LARGE: i32 = 10;
+
+// room for two of these frames but not a third
+const BUFFER_MEMORY: u64 = 2500;
+
+fn payload() -> Vec<u8> {
+    vec![0; 1000]
+}
+
+// retried frames hold their buffer memory until the final ack, a push that does not fit waits or fails
+#[tokio::test]
+async fn buffer_memory_is_held_until_the_final_outcome() {
+    let broker = Arc::new(MockBroker::start(Faults { reject_produce: Some((1, MESSAGE_TOO_LARGE)), ..Default::default() }).await);
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        retries: Some(1),
+        buffer_memory_bytes: Some(BUFFER_MEMORY),
+        buffer_full_timeout_ms: Some(300),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+    let metrics = producer.metrics();
+
+    // a rejected frame gives its memory back
+    assert!(producer.push_and_wait("budget".to_string(), "key".to_string(), payload()).await.is_err());
+    assert_eq!(metrics.buffered_bytes(), 0);
+
+    broker.pause_acks();
+    producer.push("budget".to_string(), "key".to_string(), payload()).await.unwrap();
+    producer.push("budget".to_string(), "key".to_string(), payload()).await.unwrap();
+    let held = metrics.buffered_bytes();
+    assert!(held > 2 * payload().len() as u64 && held <= BUFFER_MEMORY);
+
+    // blocking gives up at the buffer timeout, a try push fails straight away, neither keeps any memory
+    let blocked = producer.push("budget".to_string(), "key".to_string(), payload()).await;
+    assert!(matches!(blocked, Err(ProducerError::Timeout(_))), "{:?}", blocked);
+    assert!(matches!(producer.try_push("budget".to_string(), "key".to_string(), payload()), Err(ProducerError::QueueFull)));
+    assert_eq!(metrics.buffered_bytes(), held);
+
+    // an ack inside the timeout lets the blocked push through
+    let acks = Arc::clone(&broker);
+    tokio::spawn(async move {
+        sleep(Duration::from_millis(100)).await;
+        acks.resume_acks();
+    });
+    producer.push("budget".to_string(), "key".to_string(), payload()).await.unwrap();
+
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(metrics.buffered_bytes(), 0);
+    assert_eq!(broker.produced().len(), 4);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
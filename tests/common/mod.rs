@@ -0,0 +1,296 @@
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// control request codes the broker answers, everything else is acked as it is
+const API_VERSIONS: i32 = 1008;
+const INIT_PRODUCER_ID: i32 = 1001;
+const END_TXN: i32 = 1004;
+const HEARTBEAT: i32 = 1005;
+
+// produce frames are counted from 1 across every connection
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Faults {
+    // the connection carrying this frame is closed before the ack
+    pub drop_produce: Option<usize>,
+    // this frame is acked with the error code
+    pub reject_produce: Option<(usize, i32)>,
+    // behaves like a broker that predates the handshake and never answers it
+    pub ignore_api_versions: bool,
+}
+
+// a produce frame as the broker read it
+#[derive(Debug, Clone)]
+pub struct Produced {
+    pub connection: usize,
+    pub topic: String,
+    pub partition: i32,
+    pub sequence: i32,
+    pub unique_key: String,
+    pub key: String,
+    pub compression: String,
+    // empty for frames with headers, the payload follows them
+    pub payload: Vec<u8>,
+}
+
+// an in process broker speaking just enough of the protocol for the producer, it acks every frame unless told otherwise
+pub struct MockBroker {
+    pub servers: String,
+    pub produced: Arc<Mutex<Vec<Produced>>>,
+    // the commit flag of every end transaction request
+    pub end_txn: Arc<Mutex<Vec<bool>>>,
+    acks_paused: Arc<AtomicBool>,
+    heartbeats_ignored: Arc<AtomicBool>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockBroker {
+    pub async fn start(faults: Faults) -> MockBroker {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let servers = listener.local_addr().unwrap().to_string();
+        let produced = Arc::new(Mutex::new(Vec::new()));
+        let end_txn = Arc::new(Mutex::new(Vec::new()));
+        let acks_paused = Arc::new(AtomicBool::new(false));
+        let heartbeats_ignored = Arc::new(AtomicBool::new(false));
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let state = Arc::new(State {
+            produced: Arc::clone(&produced),
+            end_txn: Arc::clone(&end_txn),
+            acks_paused: Arc::clone(&acks_paused),
+            heartbeats_ignored: Arc::clone(&heartbeats_ignored),
+            produce_count: AtomicUsize::new(0),
+            faults,
+        });
+        let accepted = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let connection = accepted.fetch_add(1, Ordering::SeqCst) + 1;
+                tokio::spawn(serve(socket, connection, Arc::clone(&state)));
+            }
+        });
+
+        MockBroker { servers, produced, end_txn, acks_paused, heartbeats_ignored, connections }
+    }
+
+    pub fn produced(&self) -> Vec<Produced> {
+        self.produced.lock().unwrap().clone()
+    }
+
+    pub fn end_txn(&self) -> Vec<bool> {
+        self.end_txn.lock().unwrap().clone()
+    }
+
+    // a paused broker still reads the first frame on each connection, it holds its ack and reads nothing more
+    pub fn pause_acks(&self) {
+        self.acks_paused.store(true, Ordering::SeqCst);
+    }
+
+    pub fn resume_acks(&self) {
+        self.acks_paused.store(false, Ordering::SeqCst);
+    }
+
+    // the connection stays open and reads every ping, it just never answers one
+    pub fn ignore_heartbeats(&self) {
+        self.heartbeats_ignored.store(true, Ordering::SeqCst);
+    }
+
+    pub fn answer_heartbeats(&self) {
+        self.heartbeats_ignored.store(false, Ordering::SeqCst);
+    }
+
+    // every connection the producer opened so far, reconnects included
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+struct State {
+    produced: Arc<Mutex<Vec<Produced>>>,
+    end_txn: Arc<Mutex<Vec<bool>>>,
+    acks_paused: Arc<AtomicBool>,
+    heartbeats_ignored: Arc<AtomicBool>,
+    produce_count: AtomicUsize,
+    faults: Faults,
+}
+
+async fn serve(mut socket: TcpStream, connection: usize, state: Arc<State>) {
+    loop {
+        let mut len = [0u8; 8];
+        if socket.read_exact(&mut len).await.is_err() {
+            return;
+        }
+
+        let mut body = vec![0u8; u64::from_be_bytes(len) as usize];
+        if socket.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        let mut reader = Reader { body: &body, position: 0 };
+        let version = reader.string();
+        let topic = reader.string();
+
+        let response = if topic == "P" {
+            let code = reader.int();
+            let unique_key = reader.string();
+
+            // transactional id, producer id and epoch come before the marker
+            if code == END_TXN {
+                let _transactional_id = reader.string();
+                reader.skip(8 + 2);
+                state.end_txn.lock().unwrap().push(reader.take(1)[0] != 0);
+            }
+
+            if code == API_VERSIONS && state.faults.ignore_api_versions {
+                continue;
+            }
+            if code == HEARTBEAT && state.heartbeats_ignored.load(Ordering::SeqCst) {
+                continue;
+            }
+
+            control_response(code, &unique_key)
+        } else {
+            let _client_type = reader.string();
+            let _code = reader.int();
+            let compression = reader.string();
+            let _acks = reader.string();
+            let partition = reader.int();
+
+            // v2 and later carry the producer id, epoch, sequence and transactional flag
+            let sequence = if version == "V_1" {
+                -1
+            } else {
+                reader.skip(8 + 2);
+                let sequence = reader.int();
+                reader.skip(1);
+                sequence
+            };
+            let unique_key = reader.string();
+            let key = reader.string();
+            let payload = if version == "V_3" {
+                Vec::new()
+            } else {
+                let len = u64::from_be_bytes(reader.take(8).try_into().unwrap()) as usize;
+                reader.take(len).to_vec()
+            };
+
+            state.produced.lock().unwrap().push(Produced {
+                connection,
+                topic: topic.to_string(),
+                partition,
+                sequence,
+                unique_key: unique_key.to_string(),
+                key: key.to_string(),
+                compression,
+                payload,
+            });
+
+            let count = state.produce_count.fetch_add(1, Ordering::SeqCst) + 1;
+            if state.faults.drop_produce == Some(count) {
+                return;
+            }
+            let error_code = match state.faults.reject_produce {
+                Some((reject, error_code)) if reject == count => error_code,
+                _ => 0,
+            };
+
+            while state.acks_paused.load(Ordering::SeqCst) {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+
+            let mut response = header(error_code);
+            put_string(&mut response, &topic);
+            response.extend_from_slice(&partition.to_be_bytes());
+            put_string(&mut response, &unique_key);
+            put_string(&mut response, &key);
+            response
+        };
+
+        let mut frame = (response.len() as u64).to_be_bytes().to_vec();
+        frame.extend_from_slice(&response);
+        if socket.write_all(&frame).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn control_response(code: i32, unique_key: &str) -> Vec<u8> {
+    let mut response = header(0);
+
+    match code {
+        API_VERSIONS => {
+            // produce up to v2, init producer id, the transaction requests and heartbeat
+            let versions: [(i32, i16, i16); 6] = [(1000, 1, 2), (1001, 1, 1), (1002, 1, 1), (1003, 1, 1), (1004, 1, 1), (1005, 1, 1)];
+            response.extend_from_slice(&(versions.len() as i32).to_be_bytes());
+            for (code, min, max) in versions {
+                response.extend_from_slice(&code.to_be_bytes());
+                response.extend_from_slice(&min.to_be_bytes());
+                response.extend_from_slice(&max.to_be_bytes());
+            }
+        }
+        INIT_PRODUCER_ID => {
+            response.extend_from_slice(&7i64.to_be_bytes());
+            response.extend_from_slice(&1i16.to_be_bytes());
+        }
+        _ => {
+            put_string(&mut response, "-");
+            response.extend_from_slice(&0i32.to_be_bytes());
+            put_string(&mut response, unique_key);
+            put_string(&mut response, "-");
+        }
+    }
+
+    response
+}
+
+// P, the error code and a message
+fn header(error_code: i32) -> Vec<u8> {
+    let mut response = Vec::new();
+    put_string(&mut response, "P");
+    response.extend_from_slice(&error_code.to_be_bytes());
+    put_string(&mut response, "ok");
+    response
+}
+
+// strings carry a width marker, 1 for a one byte length up to 4 for eight bytes
+fn put_string(out: &mut Vec<u8>, value: &str) {
+    out.push(2);
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value.as_bytes());
+}
+
+struct Reader<'a> {
+    body: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> &[u8] {
+        let bytes = &self.body[self.position..self.position + len];
+        self.position += len;
+        bytes
+    }
+
+    fn skip(&mut self, len: usize) {
+        self.position += len;
+    }
+
+    fn int(&mut self) -> i32 {
+        i32::from_be_bytes(self.take(4).try_into().unwrap())
+    }
+
+    fn string(&mut self) -> String {
+        let width = match self.take(1)[0] {
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            _ => 8,
+        };
+        let len = self.take(width).iter().fold(0usize, |len, byte| (len << 8) | *byte as usize);
+        String::from_utf8_lossy(self.take(len)).to_string()
+    }
+}
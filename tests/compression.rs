@@ -0,0 +1,33 @@
+mod common;
+
+use std::time::Duration;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::compression::CompressionType;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// the codec is picked once at connect, every frame after that names it and carries a payload it can read back
+#[tokio::test]
+async fn payloads_are_compressed_with_the_codec_chosen_at_connect() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        compression_type: Some("GZIP".to_string()),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    let payload = "hello sudeep ".repeat(100).into_bytes();
+    producer.push("compressed".to_string(), "key".to_string(), payload.to_vec()).await.unwrap();
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+
+    let produced = broker.produced();
+    assert_eq!(produced.len(), 1);
+    assert_eq!(produced[0].compression, "gzip");
+    assert!(produced[0].payload.len() < payload.len());
+    assert_eq!(CompressionType::Gzip.decompress(&produced[0].payload).unwrap(), payload);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
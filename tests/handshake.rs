@@ -0,0 +1,33 @@
+mod common;
+
+use std::time::Duration;
+use tokio::time::timeout;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// a broker that never answers the api versions request is taken for one that predates it, instead of hanging the connect
+#[tokio::test]
+async fn a_silent_broker_falls_back_to_legacy_versions() {
+    let broker = MockBroker::start(Faults { ignore_api_versions: true, ..Default::default() }).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        heartbeat_interval_ms: Some(3600000),
+        heartbeat_timeout_ms: Some(200),
+        ..Default::default()
+    };
+    timeout(Duration::from_secs(5), producer.connect_producer())
+        .await
+        .expect("connect hung on the handshake")
+        .unwrap();
+
+    producer.push_and_wait("handshake".to_string(), "key".to_string(), b"legacy".to_vec()).await.unwrap();
+
+    // v1 frames carry no sequence
+    let produced = broker.produced();
+    assert_eq!(produced.len(), 1);
+    assert_eq!(produced[0].sequence, -1);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// the socket stays open but pings go unanswered, the producer has to give up on it and connect again
+#[tokio::test]
+async fn a_missed_heartbeat_deadline_reconnects() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        reconnect_backoff_ms: Some(10),
+        reconnect_backoff_max_ms: Some(50),
+        heartbeat_interval_ms: Some(50),
+        heartbeat_timeout_ms: Some(100),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    // answered pings keep the connection as it is
+    sleep(Duration::from_millis(400)).await;
+    assert_eq!(broker.connections(), 1);
+
+    broker.ignore_heartbeats();
+    timeout(Duration::from_secs(5), async {
+        while broker.connections() < 2 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("the silent connection was never replaced");
+    broker.answer_heartbeats();
+
+    timeout(Duration::from_secs(5), producer.push_and_wait("heartbeat".to_string(), "key".to_string(), b"after".to_vec()))
+        .await
+        .expect("the frame was never acked")
+        .unwrap();
+    assert_eq!(broker.produced()[0].connection, broker.connections());
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
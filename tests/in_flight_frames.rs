@@ -0,0 +1,38 @@
+mod common;
+
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::{producer_metrics, Producer};
+use common::{Faults, MockBroker};
+
+// without retries no frame is kept, written frames still count as in flight until their ack
+#[tokio::test]
+async fn in_flight_frames_count_written_frames_that_are_not_kept() {
+    let broker = MockBroker::start(Faults::default()).await;
+    broker.pause_acks();
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        retries: Some(0),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    for i in 0..3 {
+        producer.push("in_flight".to_string(), "key".to_string(), i.to_string().into_bytes()).await.unwrap();
+    }
+    timeout(Duration::from_secs(5), async {
+        while producer_metrics.in_flight_frames() < 3 {
+            sleep(Duration::from_millis(5)).await;
+        }
+    }).await.expect("the frames were never written");
+    assert!(producer_metrics.render_prometheus().contains("brahmaputra_producer_in_flight_frames 3"));
+
+    broker.resume_acks();
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(producer_metrics.in_flight_frames(), 0);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use bytes::Bytes;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::broker_error::BrokerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::enums::BackpressurePolicy;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::interceptors::ProducerInterceptor;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::{DeliveryOutcome, DeliveryReport, Producer, ProducerRecord};
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::rate_limiter::RateLimit;
+use common::{Faults, MockBroker};
+
+// message too large, the broker will not take the frame however often it is sent
+const MESSAGE_TOO_LARGE: i32 = 10;
+
+// upper cases the key, tags the payload and keeps every report it hears about
+#[derive(Debug, Default)]
+struct Recorder {
+    reports: Mutex<Vec<DeliveryReport>>,
+}
+
+impl ProducerInterceptor for Recorder {
+    fn on_send(&self, record: &mut ProducerRecord) {
+        record.key = record.key.to_uppercase();
+        record.payload = Bytes::from([b"seen:".as_slice(), &record.payload].concat());
+    }
+
+    fn on_acknowledgement(&self, report: &DeliveryReport) {
+        self.reports.lock().unwrap().push(report.clone());
+    }
+}
+
+impl Recorder {
+    fn outcomes(&self) -> HashMap<String, Vec<DeliveryOutcome>> {
+        let mut outcomes: HashMap<String, Vec<DeliveryOutcome>> = HashMap::new();
+        for report in self.reports.lock().unwrap().iter() {
+            outcomes.entry(report.topic.to_string()).or_default().push(report.outcome);
+        }
+        outcomes
+    }
+}
+
+fn producer(servers: &str, recorder: &Arc<Recorder>) -> Producer {
+    Producer {
+        servers: servers.to_string(),
+        pool: Some(1),
+        reconnect_backoff_ms: Some(10),
+        reconnect_backoff_max_ms: Some(50),
+        heartbeat_interval_ms: Some(3600000),
+        interceptors: vec![recorder.clone()],
+        ..Default::default()
+    }
+}
+
+// every message that went through on_send is reported exactly once, whichever way it ended
+#[tokio::test]
+async fn every_outcome_reaches_the_interceptors() {
+    let broker = MockBroker::start(Faults {
+        reject_produce: Some((2, MESSAGE_TOO_LARGE)),
+        drop_produce: Some(3),
+        ..Default::default()
+    }).await;
+    let recorder = Arc::new(Recorder::default());
+
+    let mut acked = Producer {
+        buffer_memory_bytes: Some(4096),
+        backpressure_policy: Some(BackpressurePolicy::DropNewest),
+        topic_rate_limits: Some(HashMap::from([("limited".to_string(), RateLimit { messages_per_sec: Some(1), bytes_per_sec: None })])),
+        ..producer(&broker.servers, &recorder)
+    };
+    acked.connect_producer().await.unwrap();
+
+    acked.push_and_wait("acked".to_string(), "key-1".to_string(), b"one".to_vec()).await.unwrap();
+    assert!(matches!(
+        acked.push_and_wait("rejected".to_string(), "key-2".to_string(), b"two".to_vec()).await,
+        Err(ProducerError::Broker(BrokerError::MessageTooLarge, _))
+    ));
+
+    // the broker closes the connection instead of acking, without retries the frame is not kept
+    assert!(matches!(
+        acked.push_and_wait("lost".to_string(), "key-3".to_string(), b"three".to_vec()).await,
+        Err(ProducerError::NotConnected)
+    ));
+
+    // larger than the whole buffer memory, push fails before anything is queued
+    assert!(matches!(acked.push("oversize".to_string(), "key-4".to_string(), vec![0; 8192]).await, Err(ProducerError::Encode(_))));
+
+    // the second message goes over the rate limit and drop newest drops it
+    acked.try_push("limited".to_string(), "key-5".to_string(), b"five".to_vec()).unwrap();
+    acked.try_push("limited".to_string(), "key-6".to_string(), b"six".to_vec()).unwrap();
+    acked.flush(Duration::from_secs(5)).await.unwrap();
+    acked.close(Duration::from_secs(5)).await.unwrap();
+
+    // acks 0 never hears back, being written is the outcome
+    let mut unacked = Producer {
+        acks: Some("0".to_string()),
+        ..producer(&broker.servers, &recorder)
+    };
+    unacked.connect_producer().await.unwrap();
+    unacked.push("unacked".to_string(), "key-7".to_string(), b"seven".to_vec()).await.unwrap();
+    unacked.flush(Duration::from_secs(5)).await.unwrap();
+    unacked.close(Duration::from_secs(5)).await.unwrap();
+
+    let outcomes = recorder.outcomes();
+    assert_eq!(outcomes["acked"], vec![DeliveryOutcome::Acked]);
+    assert_eq!(outcomes["rejected"], vec![DeliveryOutcome::Rejected(BrokerError::MessageTooLarge)]);
+    assert_eq!(outcomes["lost"], vec![DeliveryOutcome::Failed]);
+    assert_eq!(outcomes["oversize"], vec![DeliveryOutcome::Failed]);
+    let mut limited = outcomes["limited"].clone();
+    limited.sort_by_key(|outcome| format!("{:?}", outcome));
+    assert_eq!(limited, vec![DeliveryOutcome::Acked, DeliveryOutcome::Dropped]);
+    assert_eq!(outcomes["unacked"], vec![DeliveryOutcome::Written]);
+    assert_eq!(recorder.reports.lock().unwrap().len(), 7);
+
+    // on_send ran before encoding, the broker got the changed record and the report names the changed key
+    let produced = broker.produced();
+    assert_eq!(produced[0].key, "KEY-1");
+    assert_eq!(produced[0].payload, b"seen:one".to_vec());
+    let reports = recorder.reports.lock().unwrap();
+    assert_eq!(reports[0].key, "KEY-1");
+    assert!(reports[0].latency.is_some());
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::time::Duration;
+use tokio::time::timeout;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// the broker closes the connection after reading the first frame, it has to come again on the new one with its sequence
+#[tokio::test]
+async fn unacked_frames_are_sent_again_after_a_reconnect() {
+    let broker = MockBroker::start(Faults { drop_produce: Some(1), ..Default::default() }).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        enable_idempotence: Some(true),
+        retries: Some(3),
+        reconnect_backoff_ms: Some(10),
+        reconnect_backoff_max_ms: Some(50),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    timeout(Duration::from_secs(10), producer.push_and_wait("reconnect".to_string(), "key".to_string(), b"first".to_vec()))
+        .await
+        .expect("the frame was never acked")
+        .unwrap();
+    timeout(Duration::from_secs(10), producer.push_and_wait("reconnect".to_string(), "key".to_string(), b"second".to_vec()))
+        .await
+        .expect("the frame was never acked")
+        .unwrap();
+
+    let produced = broker.produced();
+    assert_eq!(produced.len(), 3);
+    assert_eq!(produced[0].unique_key, produced[1].unique_key);
+    assert_ne!(produced[0].connection, produced[1].connection);
+    assert_eq!((produced[0].sequence, produced[1].sequence, produced[2].sequence), (0, 0, 1));
+
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::pin::Pin;
+use std::time::Duration;
+use bytes::Bytes;
+use futures::{stream, Sink, SinkExt};
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_sink::ProducerSink;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::{Producer, ProducerRecord};
+use common::{Faults, MockBroker};
+
+fn record(i: u32) -> ProducerRecord {
+    ProducerRecord::new("sink".to_string(), "key".to_string(), Bytes::from(i.to_string()))
+}
+
+// send_all, flush, a push that is still running and close, all against one producer
+#[tokio::test]
+async fn sink_pushes_flushes_and_closes() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+    let mut sink = ProducerSink::new(producer, Duration::from_secs(5));
+
+    let mut records = stream::iter((0..10).map(|i| Ok(record(i))));
+    sink.send_all(&mut records).await.unwrap();
+    assert_eq!(broker.produced().len(), 10);
+
+    sink.feed(record(10)).await.unwrap();
+    assert!(matches!(Pin::new(&mut sink).start_send(record(11)), Err(ProducerError::SinkNotReady)));
+
+    sink.flush().await.unwrap();
+    let payloads: Vec<Vec<u8>> = broker.produced().into_iter().map(|produced| produced.payload).collect();
+    let expected: Vec<Vec<u8>> = (0..11).map(|i: u32| i.to_string().into_bytes()).collect();
+    assert_eq!(payloads, expected);
+
+    sink.close().await.unwrap();
+    let mut producer = sink.into_inner().expect("the producer is handed back after close");
+    assert!(matches!(producer.push_record(record(12)).await, Err(ProducerError::NotConnected)));
+}
@@ -0,0 +1,51 @@
+mod common;
+
+use std::fs;
+use std::time::Duration;
+use uuid::Uuid;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// room for two of these frames but not a third
+const BUFFER_MEMORY: u64 = 2500;
+
+fn payload(i: u8) -> Vec<u8> {
+    vec![i; 1000]
+}
+
+// pushes that do not fit in memory go to disk and come back in order once acks free the memory
+#[tokio::test]
+async fn spilled_messages_are_synced_and_replayed_in_order() {
+    let broker = MockBroker::start(Faults::default()).await;
+    let dir = std::env::temp_dir().join(format!("brahmaputra-spill-{}", Uuid::new_v4()));
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        retries: Some(1),
+        buffer_memory_bytes: Some(BUFFER_MEMORY),
+        spill_dir: Some(dir.to_string_lossy().to_string()),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+    let metrics = producer.metrics();
+
+    broker.pause_acks();
+    for i in 0..6 {
+        producer.push("spill".to_string(), "key".to_string(), payload(i)).await.unwrap();
+    }
+    // a blocking push returns once its frame is synced, so the journal holds the four that did not fit
+    assert!(metrics.spilled_bytes() > 4 * payload(0).len() as u64);
+
+    broker.resume_acks();
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+
+    let payloads: Vec<u8> = broker.produced().iter().map(|produced| produced.payload[0]).collect();
+    assert_eq!(payloads, (0..6).collect::<Vec<u8>>());
+    assert_eq!(metrics.spilled_bytes(), 0);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use std::time::Duration;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producer_error::ProducerError;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// message too large, the broker will not take the frame however often it is sent
+const MESSAGE_TOO_LARGE: i32 = 10;
+
+// the broker rejects the second message for good, so the transaction cannot be committed and has to be aborted
+#[tokio::test]
+async fn a_failed_message_forces_an_abort() {
+    let broker = MockBroker::start(Faults { reject_produce: Some((2, MESSAGE_TOO_LARGE)), ..Default::default() }).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        transactional_id: Some("transactions-test".to_string()),
+        retries: Some(3),
+        transaction_timeout_ms: Some(5000),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    producer.begin_transaction().await.unwrap();
+    producer.send("transactions".to_string(), "key".to_string(), b"first".to_vec()).await.unwrap();
+    producer.send("transactions".to_string(), "key".to_string(), b"second".to_vec()).await.unwrap();
+
+    assert!(matches!(producer.commit_transaction().await, Err(ProducerError::TransactionFailed)));
+    assert!(matches!(producer.commit_transaction().await, Err(ProducerError::TransactionFailed)));
+    assert!(matches!(producer.send("transactions".to_string(), "key".to_string(), b"third".to_vec()).await, Err(ProducerError::InvalidTransactionState(_))));
+
+    producer.abort_transaction().await.unwrap();
+    assert_eq!(broker.end_txn(), vec![false]);
+
+    // the next transaction starts clean
+    producer.begin_transaction().await.unwrap();
+    producer.send("transactions".to_string(), "key".to_string(), b"fourth".to_vec()).await.unwrap();
+    producer.commit_transaction().await.unwrap();
+    assert_eq!(broker.end_txn(), vec![false, true]);
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,42 @@
+mod common;
+
+use std::time::Duration;
+use serde::Serialize;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::typed_producer::TypedProducer;
+use common::{Faults, MockBroker};
+
+#[derive(Serialize)]
+struct Order {
+    id: u64,
+}
+
+// a typed push and a plain push with the same key have to land on the same partition
+#[tokio::test]
+async fn typed_and_plain_pushes_pick_the_same_partition() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer: TypedProducer<String, Order> = TypedProducer::new(Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    });
+    producer.connect_producer().await.unwrap();
+
+    let keys: Vec<String> = (0..10).map(|i| format!("customer-{}", i)).collect();
+    for (id, key) in keys.iter().enumerate() {
+        producer.push("orders".to_string(), key, &Order { id: id as u64 }).await.unwrap();
+        producer.producer().push("orders".to_string(), key.to_string(), b"{}".to_vec()).await.unwrap();
+    }
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+
+    let produced = broker.produced();
+    assert_eq!(produced.len(), 2 * keys.len());
+    for pair in produced.chunks(2) {
+        assert_eq!(pair[0].partition, pair[1].partition);
+    }
+    assert_eq!(produced[0].payload, br#"{"id":0}"#.to_vec());
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}
@@ -0,0 +1,41 @@
+mod common;
+
+use std::time::Duration;
+use bytes::Bytes;
+use brahmaputra_rust_client::brahmaputra::byte_buffers::concrete_functions::producers_objects::Producer;
+use common::{Faults, MockBroker};
+
+// a payload written straight from the caller's buffer arrives byte for byte, whichever push it took
+#[tokio::test]
+async fn zero_copy_payloads_reach_the_broker_unchanged() {
+    let broker = MockBroker::start(Faults::default()).await;
+
+    let mut producer = Producer {
+        servers: broker.servers.to_string(),
+        pool: Some(1),
+        heartbeat_interval_ms: Some(3600000),
+        ..Default::default()
+    };
+    producer.connect_producer().await.unwrap();
+
+    // every byte value, large enough to need more than one write
+    let payload: Vec<u8> = (0..1 << 20).map(|i| (i % 251) as u8).collect();
+    let shared = Bytes::from(payload.clone());
+
+    producer.push_bytes("zero_copy", "bytes", shared.clone()).await.unwrap();
+    // pushing the same buffer again shares it instead of copying it
+    producer.push_bytes("zero_copy", "bytes", shared.clone()).await.unwrap();
+    producer.push_slice("zero_copy", "slice", &payload).await.unwrap();
+    producer.push_slice("zero_copy", "empty", &[]).await.unwrap();
+    producer.flush(Duration::from_secs(5)).await.unwrap();
+
+    let produced = broker.produced();
+    let keys: Vec<&str> = produced.iter().map(|produced| produced.key.as_str()).collect();
+    assert_eq!(keys, vec!["bytes", "bytes", "slice", "empty"]);
+    for produced in &produced[..3] {
+        assert_eq!(produced.payload, payload);
+    }
+    assert!(produced[3].payload.is_empty());
+
+    producer.close(Duration::from_secs(5)).await.unwrap();
+}